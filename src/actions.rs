@@ -0,0 +1,1680 @@
+use failure::err_msg;
+use failure::Error;
+
+use i3ipc::reply::Workspace;
+use i3ipc::reply::Workspaces;
+
+use command::Command;
+use config::Config;
+use go_to;
+use history::History;
+use menu::menu_from_name;
+use parser::title_from_name;
+use protocol::Action;
+use protocol::Request;
+use protocol::Response;
+use send_to;
+use session::SavedWindow;
+use session::Session;
+use unpinned_numbers;
+use wm::Window;
+use wm::WindowManager;
+use AutoTitles;
+use WorkspaceExt;
+use WorkspacesExt;
+
+// i3 keeps workspace numbers in an i32, so larger positions can't name a workspace
+const MAX_NUMBER: usize = i32::MAX as usize;
+
+// renumber the workspaces so their numbers match their positions in the list
+pub fn fixup(wm: &mut dyn WindowManager, config: &Config) -> Result<(), Error> {
+    // initialize vector of fixup commands
+    let fixup_cmds =
+        wm.get_workspaces()?
+            .fixup_wps(&config.separator, &config.pins, config.numbering());
+
+    // run fixup commands all at once
+    wm.run_commands(&fixup_cmds)
+}
+
+// bring the focus history up to date with the window manager's list of workspaces, and record the focused
+// workspace as the most recently used one if focus just changed
+pub fn update_history(
+    wm: &mut dyn WindowManager,
+    history: &mut History,
+    config: &Config,
+    focus_changed: bool,
+) -> Result<(), Error> {
+    let workspaces = wm.get_workspaces()?;
+    history.sync(&workspaces, &config.separator);
+    if focus_changed {
+        if let Some(wp) = workspaces.get_wp_with_focus() {
+            history.focus(wp.title(&config.separator), &wp.output);
+        }
+    }
+    Ok(())
+}
+
+// move a newly opened window to the workspace its rule names, creating the workspace the same way
+// send_to would; windows that no rule matches stay where they opened
+pub fn route_window(wm: &mut dyn WindowManager, config: &Config, id: i64) -> Result<(), Error> {
+    if config.rules.is_empty() {
+        return Ok(());
+    }
+    // the window may already be gone again
+    let window = match wm.get_windows()?.into_iter().find(|w| w.id == id) {
+        Some(window) => window,
+        None => return Ok(()),
+    };
+    let title = match config.rule_for(&window) {
+        Some(rule) => rule.workspace.trim(),
+        None => return Ok(()),
+    };
+
+    let separator = config.separator.as_str();
+    let workspaces = wm.get_workspaces()?;
+    if title_from_name(&window.workspace, separator)? == title {
+        return Ok(());
+    }
+    // new workspaces appear on the focused output
+    let name = workspaces.name_for_title(
+        title,
+        separator,
+        &config.pins,
+        config.numbering(),
+        focused_output(&workspaces),
+    );
+    wm.run_commands(&[Command::MoveWindowToWorkspace {
+        id,
+        workspace: name,
+    }])?;
+    fixup(wm, config)
+}
+
+// give workspaces whose title is just a number a title made of the classes of their windows
+// ("firefox", "alacritty+emacs"), and keep the titles given this way up to date as windows come and
+// go; a workspace whose title the user chose is never renamed
+pub fn auto_title(
+    wm: &mut dyn WindowManager,
+    history: &mut History,
+    config: &Config,
+    auto_titled: &mut AutoTitles,
+) -> Result<(), Error> {
+    if !config.auto_titles {
+        return Ok(());
+    }
+    let separator = config.separator.as_str();
+    let workspaces = wm.get_workspaces()?;
+    let windows = wm.get_windows()?;
+
+    // titles that are gone were either closed or renamed by the user
+    auto_titled.retain(|title| workspaces.get_wp_with_title(title, separator).is_some());
+
+    let mut taken: Vec<String> = workspaces
+        .workspaces
+        .iter()
+        .map(|wp| wp.title(separator).to_owned())
+        .collect();
+    let mut cmds = vec![];
+    for (i, wp) in workspaces.workspaces.iter().enumerate() {
+        let old_title = wp.title(separator);
+        // a name that is just a number has no title at all; "1:42" is a title the user chose
+        let untitled = wp.name == old_title && old_title.parse::<i32>().is_ok();
+        if wp.num < 0 || !(untitled || auto_titled.contains(old_title)) {
+            continue;
+        }
+
+        let mut classes: Vec<String> = vec![];
+        for class in windows
+            .iter()
+            .filter(|w| w.workspace == wp.name)
+            .filter_map(|w| w.class.as_ref())
+        {
+            let class = class.to_lowercase();
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+
+        let (title, cmd) = if classes.is_empty() {
+            // the focused workspace stays open after its last window closes; it goes back to
+            // being just a number
+            if untitled {
+                continue;
+            }
+            let title = wp.num.to_string();
+            let cmd = Command::RenameWorkspace {
+                from: wp.name.clone(),
+                to: title.clone(),
+            };
+            auto_titled.remove(old_title);
+            (title, cmd)
+        } else {
+            // titles have to be unique, so windows of the same classes on another workspace get a
+            // suffix
+            let base = classes.join("+");
+            let mut title = base.clone();
+            let mut suffix = 2;
+            while taken.iter().enumerate().any(|(j, t)| j != i && *t == title) {
+                title = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            if title == old_title {
+                continue;
+            }
+            auto_titled.remove(old_title);
+            auto_titled.insert(title.clone());
+            let cmd = wp.rename(&title, separator);
+            (title, cmd)
+        };
+        history.rename(old_title, &title);
+        taken[i] = title;
+        cmds.push(cmd);
+    }
+
+    wm.run_commands(&cmds)
+}
+
+// describe the current workspaces and their windows, for saving
+pub fn snapshot(wm: &mut dyn WindowManager, config: &Config) -> Result<Session, Error> {
+    let workspaces = wm.get_workspaces()?;
+    let windows = wm.get_windows()?;
+    Ok(Session::capture(&workspaces, &windows, &config.separator))
+}
+
+// bring back the workspaces of a saved session: every window that matches one that was saved is
+// moved to a workspace with the title it was on, and those workspaces go back to their outputs.
+// workspaces none of whose windows are open can't be recreated, since i3 closes empty workspaces
+pub fn restore(
+    wm: &mut dyn WindowManager,
+    config: &Config,
+    session: Option<&Session>,
+) -> Result<Response, Error> {
+    let session = match session {
+        Some(session) => session,
+        None => return Err(err_msg("There is no saved session to restore")),
+    };
+    let separator = config.separator.as_str();
+    let workspaces = wm.get_workspaces()?;
+    let windows = wm.get_windows()?;
+
+    // every saved window, with the index of the saved workspace it was on
+    let saved: Vec<(usize, &SavedWindow)> = session
+        .workspaces
+        .iter()
+        .enumerate()
+        .flat_map(|(i, wp)| wp.windows.iter().map(move |w| (i, w)))
+        .collect();
+
+    // pair saved windows with open ones, most exact matches first, so a window whose title
+    // changed can still be told apart from others of its class; windows that are already on the
+    // right workspace are preferred, so they don't get swapped around
+    let mut homes: Vec<Option<usize>> = vec![None; windows.len()];
+    let mut found = vec![false; saved.len()];
+    for strictness in (0..3).rev() {
+        for (j, &(i, saved_window)) in saved.iter().enumerate() {
+            if found[j] {
+                continue;
+            }
+            let title = session.workspaces[i].title.as_str();
+            let candidates = || {
+                (0..windows.len()).filter(|&k| {
+                    homes[k].is_none() && same_window(saved_window, &windows[k], strictness)
+                })
+            };
+            let in_place = candidates().find(|&k| {
+                workspaces
+                    .get_wp_with_name(&windows[k].workspace)
+                    .is_some_and(|wp| wp.title(separator) == title)
+            });
+            if let Some(k) = in_place.or_else(|| candidates().next()) {
+                homes[k] = Some(i);
+                found[j] = true;
+            }
+        }
+    }
+
+    let mut cmds = vec![];
+    for (window, home) in windows.iter().zip(&homes) {
+        let saved_wp = match *home {
+            Some(i) => &session.workspaces[i],
+            None => continue,
+        };
+        let name = match workspaces.get_wp_with_title(&saved_wp.title, separator) {
+            Some(wp) if wp.name == window.workspace => continue,
+            Some(wp) => wp.name.clone(),
+            // bare numbers are their own title
+            None if saved_wp.number < 0 || saved_wp.title == saved_wp.number.to_string() => {
+                saved_wp.title.clone()
+            }
+            None => format!("{}{}{}", saved_wp.number, separator, saved_wp.title),
+        };
+        cmds.push(Command::MoveWindowToWorkspace {
+            id: window.id,
+            workspace: name,
+        });
+    }
+    wm.run_commands(&cmds)?;
+
+    // new workspaces were created on the focused output; outputs that aren't connected any more
+    // are left alone
+    let outputs = wm.get_outputs()?.outputs;
+    let workspaces = wm.get_workspaces()?;
+    let mut cmds = vec![];
+    for saved_wp in &session.workspaces {
+        if !outputs
+            .iter()
+            .any(|o| o.active && o.name == saved_wp.output)
+        {
+            continue;
+        }
+        if let Some(wp) = workspaces.get_wp_with_title(&saved_wp.title, separator) {
+            if wp.output != saved_wp.output {
+                cmds.push(Command::MoveWorkspaceToOutput {
+                    workspace: wp.name.clone(),
+                    output: saved_wp.output.clone(),
+                });
+            }
+        }
+    }
+    wm.run_commands(&cmds)?;
+
+    fixup(wm, config)?;
+    focused_response(wm)
+}
+
+// whether an open window looks like a saved one: the class must always match, then the instance
+// and finally the title too as `strictness` goes from 0 to 2
+fn same_window(saved: &SavedWindow, window: &Window, strictness: usize) -> bool {
+    saved.class.is_some()
+        && saved.class == window.class
+        && (strictness < 1 || saved.instance == window.instance)
+        && (strictness < 2 || saved.title == window.title)
+}
+
+// carry out a client's request; reload and restore requests need the daemon's own state, so the
+// daemon handles those itself
+pub fn handle_request(
+    wm: &mut dyn WindowManager,
+    history: &mut History,
+    config: &Config,
+    request: &Request,
+) -> Result<Response, Error> {
+    let separator = config.separator.as_str();
+
+    // get Workspaces object from the window manager
+    let workspaces = wm.get_workspaces()?;
+
+    if let Some(ref output) = request.output {
+        check_output(wm, output)?;
+    }
+
+    // new workspaces are numbered for the output they're created on
+    let focused_output = focused_output(&workspaces);
+    let output = match request.output {
+        Some(ref output) => output.as_str(),
+        None => focused_output,
+    };
+    let numbering = config.numbering();
+
+    // establish the target workspace name (or title) for this action
+    let target = match request.action {
+        Action::Reload | Action::Restore => {
+            return Err(err_msg(format!(
+                "The {} action must be handled by the daemon",
+                request.action
+            )))
+        }
+
+        // if the action is "toggle"
+        Action::Toggle => {
+            if request.target.is_some() {
+                return Err(err_msg("The toggle action doesn't take a target"));
+            }
+
+            // determine the currently focused workspace
+            let current = match workspaces.get_wp_with_focus() {
+                Some(wp) => wp,
+                None => return Err(err_msg("Couldn't find a focused workspace")),
+            };
+
+            // determine the name of the workspace that was focused before the current one, on the
+            // same output if the request asks for it
+            history.sync(&workspaces, separator);
+            let on_output = if request.has_flag("current_output") {
+                Some(current.output.as_str())
+            } else {
+                None
+            };
+            match history.previous(current.title(separator), on_output) {
+                Some(title) => {
+                    workspaces.name_for_title(title, separator, &config.pins, numbering, output)
+                }
+                None => return Err(err_msg("Couldn't find a previously focused workspace")),
+            }
+        }
+
+        // step through the workspaces in the order the menu would list them
+        Action::Next | Action::Prev => {
+            if request.target.is_some() {
+                return Err(err_msg(format!(
+                    "The {} action doesn't take a target",
+                    request.action
+                )));
+            }
+            let current = match workspaces.get_wp_with_focus() {
+                Some(wp) => wp.title(separator),
+                None => return Err(err_msg("Couldn't find a focused workspace")),
+            };
+
+            let entries = menu_entries(&workspaces, history, config, request);
+            let titles: Vec<&str> = entries.iter().map(|entry| entry.1).collect();
+            match history.step(&titles, current, request.action == Action::Next) {
+                // going to the focused workspace could make i3 go back and forth instead
+                Some(ref title) if title != current => {
+                    workspaces.name_for_title(title, separator, &config.pins, numbering, output)
+                }
+                _ => return focused_response(wm),
+            }
+        }
+
+        // renaming doesn't move anything, so it has nothing in common with the other actions
+        Action::Rename => return rename_focused(wm, history, config, request, &workspaces),
+        Action::Merge => return merge(wm, history, config, request, &workspaces),
+        Action::MoveUp | Action::MoveDown | Action::MoveTo => {
+            return reorder(wm, config, request, &workspaces)
+        }
+        Action::MoveToOutput => return move_to_output(wm, config, request, &workspaces),
+
+        // the *_index actions target a workspace by its number, like i3's "workspace number"
+        Action::GoToIndex | Action::SendToIndex | Action::BringToIndex => {
+            let position = match request.position {
+                Some(0) => return Err(err_msg("Workspace numbers start at 1")),
+                Some(position) if position > MAX_NUMBER => {
+                    return Err(err_msg(format!(
+                        "Workspace numbers go up to {}",
+                        MAX_NUMBER
+                    )))
+                }
+                Some(position) => position,
+                None => {
+                    return Err(err_msg(format!(
+                        "The {} action needs a position",
+                        request.action
+                    )))
+                }
+            };
+            match workspaces.get_wp_at_position(
+                position,
+                separator,
+                &config.pins,
+                numbering,
+                output,
+            ) {
+                Some(wp) => wp.name.clone(),
+                None => {
+                    return Err(err_msg(format!(
+                        "There is no workspace number {}",
+                        position
+                    )))
+                }
+            }
+        }
+
+        // if the action isn't "toggle", the target title is either given in the request or we
+        // have to ask the user to specify it
+        _ => {
+            // show the list of workspaces in the menu and get the title chosen by the user
+            let entries = menu_entries(&workspaces, history, config, request);
+            let title = match title_from_request(request, config, &entries)? {
+                Some(title) => title,
+                // if the user didn't actually choose a target then we don't need to do
+                // anything else and can return early
+                None => return Ok(Response::Cancelled),
+            };
+
+            // count the choice for the "frequency" menu order
+            history.choose(&title);
+
+            // the target is the name of the existing workspace with that title, or the name of a
+            // new workspace to create
+            workspaces.name_for_title(&title, separator, &config.pins, numbering, output)
+        }
+    };
+
+    // a new workspace is created on the focused output unless the request asks for another one;
+    // existing workspaces stay where they are
+    let new_output = match request.output {
+        Some(ref output) if workspaces.get_wp_with_name(&target).is_none() => Some(output),
+        _ => None,
+    };
+    // a workspace that just received a window can be moved by matching that window
+    let placement: Vec<Command> = new_output
+        .iter()
+        .map(|output| Command::MoveWorkspaceToOutput {
+            workspace: target.clone(),
+            output: (*output).clone(),
+        })
+        .collect();
+
+    // initialize empty vector of action commands
+    let mut action_cmds: Vec<Command> = vec![];
+
+    // push command strings into the vector according to the requested action
+    match request.action {
+        Action::GoTo | Action::Toggle | Action::GoToIndex | Action::Next | Action::Prev => {
+            // focusing the output first makes the workspace command create it there
+            if let Some(output) = new_output {
+                action_cmds.push(Command::FocusOutput(output.clone()));
+            }
+            action_cmds.push(go_to(&target));
+        }
+        Action::SendTo | Action::SendToIndex => {
+            action_cmds.push(send_to(&target));
+            action_cmds.extend(placement);
+        }
+        Action::BringTo | Action::BringToIndex => {
+            action_cmds.push(send_to(&target));
+            action_cmds.extend(placement);
+            action_cmds.push(go_to(&target));
+        }
+        _ => unreachable!(),
+    }
+
+    // a workspace we just created and went to gets its template; the layout is loaded first so
+    // that its placeholders can take in the windows of the programs
+    let created = workspaces.get_wp_with_name(&target).is_none();
+    let focuses_target = !matches!(request.action, Action::SendTo | Action::SendToIndex);
+    if created && focuses_target {
+        if let Some(template) = config.template_for(title_from_name(&target, separator)?) {
+            action_cmds.extend(template.layout.iter().cloned().map(Command::AppendLayout));
+            action_cmds.extend(template.exec.iter().cloned().map(Command::Exec));
+        }
+    }
+
+    // run action commands all at once
+    wm.run_commands(&action_cmds)?;
+
+    // initialize vector of promotion commands; pinned workspaces aren't promoted, and neither
+    // are workspaces reached by number or by stepping through them, so that the numbers bound to
+    // keys keep pointing at the same workspaces and the steps go through the workspaces in order
+    let keep_order = matches!(
+        request.action,
+        Action::GoToIndex
+            | Action::SendToIndex
+            | Action::BringToIndex
+            | Action::Next
+            | Action::Prev
+    );
+    let promote_cmds: Vec<Command> = match wm.get_workspaces()?.get_wp_with_focus() {
+        Some(wp) if !keep_order => wp.promote(separator, &config.pins).into_iter().collect(),
+        _ => vec![],
+    };
+
+    // run promotion commands all at once
+    wm.run_commands(&promote_cmds)?;
+
+    // renumber the workspaces
+    fixup(wm, config)?;
+
+    // tell the client which workspace ended up focused
+    focused_response(wm)
+}
+
+// give the focused workspace a new title, keeping its number
+fn rename_focused(
+    wm: &mut dyn WindowManager,
+    history: &mut History,
+    config: &Config,
+    request: &Request,
+    workspaces: &Workspaces,
+) -> Result<Response, Error> {
+    let separator = config.separator.as_str();
+
+    let current = match workspaces.get_wp_with_focus() {
+        Some(wp) => wp,
+        None => return Err(err_msg("Couldn't find a focused workspace")),
+    };
+    let old_title = current.title(separator);
+
+    // the current title is the only choice in the menu, so it can be completed and edited
+    let title = match title_from_request(request, config, &[(old_title.to_owned(), old_title)])? {
+        Some(title) => title,
+        None => return Ok(Response::Cancelled),
+    };
+    if title == old_title {
+        return focused_response(wm);
+    }
+    if workspaces.get_wp_with_title(&title, separator).is_some() {
+        return Err(err_msg(format!(
+            "There already is a workspace titled {:?}",
+            title
+        )));
+    }
+
+    // the history has to know before the rename event arrives, or the workspace would lose its
+    // place in it
+    history.rename(old_title, &title);
+    wm.run_commands(&[current.rename(&title, separator)])?;
+
+    fixup(wm, config)?;
+    focused_response(wm)
+}
+
+// move every window from the source workspace (the focused one unless the request names another)
+// to the target, and follow them there; the emptied source workspace disappears
+fn merge(
+    wm: &mut dyn WindowManager,
+    history: &mut History,
+    config: &Config,
+    request: &Request,
+    workspaces: &Workspaces,
+) -> Result<Response, Error> {
+    let separator = config.separator.as_str();
+
+    let source = named_or_focused(workspaces, &request.source, separator)?;
+    let source_title = source.title(separator);
+
+    // the source can't be merged into itself, so it isn't offered in the menu
+    let entries: Vec<(String, &str)> = menu_entries(workspaces, history, config, request)
+        .into_iter()
+        .filter(|&(_, title)| title != source_title)
+        .collect();
+    let title = match title_from_request(request, config, &entries)? {
+        Some(title) => title,
+        None => return Ok(Response::Cancelled),
+    };
+    if title == source_title {
+        return Err(err_msg("Can't merge a workspace into itself"));
+    }
+
+    history.choose(&title);
+    // a new target is created on the focused output
+    let target = workspaces.name_for_title(
+        &title,
+        separator,
+        &config.pins,
+        config.numbering(),
+        focused_output(workspaces),
+    );
+
+    // only follow the windows if they were taken from the focused workspace
+    let mut cmds = vec![Command::MoveWorkspaceContents {
+        from: source.name.clone(),
+        to: target.clone(),
+    }];
+    if source.focused {
+        cmds.push(go_to(&target));
+    }
+    wm.run_commands(&cmds)?;
+
+    if let Some(wp) = wm.get_workspaces()?.get_wp_with_focus() {
+        if let Some(cmd) = wp.promote(separator, &config.pins) {
+            wm.run_commands(&[cmd])?;
+        }
+    }
+    fixup(wm, config)?;
+    focused_response(wm)
+}
+
+// move the focused workspace (or the one named in the request) to another place among the unpinned
+// workspaces numbered together with it, renumbering the ones it passes
+fn reorder(
+    wm: &mut dyn WindowManager,
+    config: &Config,
+    request: &Request,
+    workspaces: &Workspaces,
+) -> Result<Response, Error> {
+    let separator = config.separator.as_str();
+    let pins = &config.pins;
+    let numbering = config.numbering();
+
+    let subject = named_or_focused(workspaces, &request.target, separator)?;
+    let title = subject.title(separator);
+    if let Some(number) = pins.get(title) {
+        return Err(err_msg(format!(
+            "The workspace titled {:?} is pinned to number {}",
+            title, number
+        )));
+    }
+
+    let (first, titles) = workspaces.unpinned_group(title, separator, pins, numbering);
+    let index = titles.iter().position(|t| *t == title).unwrap();
+    let new_index = match request.action {
+        // the first workspace can't move up any further
+        Action::MoveUp if index == 0 => return focused_response(wm),
+        Action::MoveUp => index - 1,
+        Action::MoveDown => index + 1,
+        Action::MoveTo => {
+            let number = match request.position {
+                Some(0) => return Err(err_msg("Workspace numbers start at 1")),
+                Some(number) if number > MAX_NUMBER => {
+                    return Err(err_msg(format!(
+                        "Workspace numbers go up to {}",
+                        MAX_NUMBER
+                    )))
+                }
+                Some(number) => number,
+                None => return Err(err_msg("The move_to action needs a position")),
+            };
+            if let Some((pinned, _)) = pins.iter().find(|&(_, &n)| n == number) {
+                return Err(err_msg(format!(
+                    "Number {} is pinned to {:?}",
+                    number, pinned
+                )));
+            }
+            if number < first {
+                return Err(err_msg(format!(
+                    "Numbers on the output of {:?} start at {}",
+                    title, first
+                )));
+            }
+            // pinned numbers are skipped when numbering the others; any number past the last one
+            // moves the workspace to the end, so there's no need to count all the way up to it
+            let number = number.min(first + titles.len() + pins.len());
+            unpinned_numbers(pins, first)
+                .take_while(|&n| n < number)
+                .count()
+        }
+        _ => unreachable!(),
+    };
+
+    wm.run_commands(&workspaces.move_wp(title, new_index, separator, pins, numbering))?;
+    focused_response(wm)
+}
+
+// move the focused workspace (or the one named in the request) to the output given in the request
+// or picked from the menu, and renumber the workspaces for their new order
+fn move_to_output(
+    wm: &mut dyn WindowManager,
+    config: &Config,
+    request: &Request,
+    workspaces: &Workspaces,
+) -> Result<Response, Error> {
+    let subject = named_or_focused(workspaces, &request.target, config.separator.as_str())?;
+
+    let output = match request.output {
+        Some(ref output) => output.clone(),
+        None => {
+            // the workspace's own output isn't worth offering
+            let outputs = wm.get_outputs()?.outputs;
+            let entries: Vec<(String, &str)> = outputs
+                .iter()
+                .filter(|o| o.active && o.name != subject.output)
+                .map(|o| (o.name.clone(), o.name.as_str()))
+                .collect();
+            match choose_from_menu(request, config, &entries)? {
+                Some(output) => {
+                    check_output(wm, &output)?;
+                    output
+                }
+                None => return Ok(Response::Cancelled),
+            }
+        }
+    };
+    if output == subject.output {
+        return focused_response(wm);
+    }
+
+    // criteria only match workspaces with windows on them, but the focused workspace can be
+    // moved without them; any other workspace without windows is closed by i3 anyway, unless it
+    // is the one shown on its output
+    let cmd = if subject.focused {
+        Command::MoveFocusedWorkspaceToOutput(output)
+    } else {
+        Command::MoveWorkspaceToOutput {
+            workspace: subject.name.clone(),
+            output,
+        }
+    };
+    wm.run_commands(&[cmd])?;
+
+    // the workspaces are listed output by output, so their order has changed
+    fixup(wm, config)?;
+    focused_response(wm)
+}
+
+// the workspace with the given title, or the focused one if there is no title
+fn named_or_focused<'a>(
+    workspaces: &'a Workspaces,
+    title: &Option<String>,
+    separator: &str,
+) -> Result<&'a Workspace, Error> {
+    match *title {
+        Some(ref title) => match workspaces.get_wp_with_title(title.trim(), separator) {
+            Some(wp) => Ok(wp),
+            None => Err(err_msg(format!(
+                "There is no workspace titled {:?}",
+                title.trim()
+            ))),
+        },
+        None => match workspaces.get_wp_with_focus() {
+            Some(wp) => Ok(wp),
+            None => Err(err_msg("Couldn't find a focused workspace")),
+        },
+    }
+}
+
+// the output of the focused workspace
+fn focused_output(workspaces: &Workspaces) -> &str {
+    match workspaces.get_wp_with_focus() {
+        Some(wp) => &wp.output,
+        None => "",
+    }
+}
+
+// fail unless the window manager has an active output with this name
+fn check_output(wm: &mut dyn WindowManager, output: &str) -> Result<(), Error> {
+    if wm
+        .get_outputs()?
+        .outputs
+        .iter()
+        .any(|o| o.active && o.name == output)
+    {
+        Ok(())
+    } else {
+        Err(err_msg(format!("There is no output named {:?}", output)))
+    }
+}
+
+// the lines of the menu, each with the title of the workspace it stands for; the request's flags
+// can leave out the workspaces on other outputs and add each workspace's output to its line
+fn menu_entries<'a>(
+    workspaces: &'a Workspaces,
+    history: &History,
+    config: &Config,
+    request: &Request,
+) -> Vec<(String, &'a str)> {
+    let separator = config.separator.as_str();
+    let order = request.order.unwrap_or(config.order);
+    // with only the focused output's workspaces listed, its own history decides their order
+    let on_output = match workspaces.get_wp_with_focus() {
+        Some(wp) if request.has_flag("current_output") => Some(wp.output.as_str()),
+        _ => None,
+    };
+
+    workspaces
+        .sorted_wps(order, history, separator, on_output)
+        .into_iter()
+        .filter(|wp| on_output.is_none() || Some(wp.output.as_str()) == on_output)
+        .map(|wp| {
+            let title = wp.title(separator);
+            let line = if request.has_flag("show_outputs") {
+                format!("{} ({})", title, wp.output)
+            } else {
+                title.to_owned()
+            };
+            (line, title)
+        })
+        .collect()
+}
+
+// the title given in the request, or the one the user picks from the menu (None if they dismiss
+// it); `entries` are the lines shown in the menu, each with the title it stands for
+fn title_from_request(
+    request: &Request,
+    config: &Config,
+    entries: &[(String, &str)],
+) -> Result<Option<String>, Error> {
+    match request.target {
+        Some(ref title) => match title.trim() {
+            "" => Err(err_msg("The target title can't be empty")),
+            title => Ok(Some(title.to_owned())),
+        },
+        None => choose_from_menu(request, config, entries),
+    }
+}
+
+// show the lines of `entries` in the menu requested by the client and return what the chosen line
+// stands for; a line that isn't in the menu is returned as typed by the user
+fn choose_from_menu(
+    request: &Request,
+    config: &Config,
+    entries: &[(String, &str)],
+) -> Result<Option<String>, Error> {
+    // look up the menu program requested by the client
+    let menu_name = request.menu.as_ref().unwrap_or(&config.menu);
+    let menu = menu_from_name(menu_name, config.menu_args(menu_name))?;
+    let choices = entries
+        .iter()
+        .map(|entry| entry.0.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    Ok(menu.choose(&choices)?.map(
+        |choice| match entries.iter().find(|entry| entry.0 == choice) {
+            Some(&(_, value)) => value.to_owned(),
+            None => choice,
+        },
+    ))
+}
+
+// a successful response naming the workspace that has focus now
+fn focused_response(wm: &mut dyn WindowManager) -> Result<Response, Error> {
+    Ok(Response::Success {
+        focused: wm
+            .get_workspaces()?
+            .get_wp_with_focus()
+            .map(|wp| wp.name.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        auto_title, fixup, handle_request, menu_entries, restore, route_window, snapshot,
+        update_history,
+    };
+    use command::Command;
+    use config::{Config, NumberingMode};
+    use history::{History, Order};
+    use mock::MockWm;
+    use protocol::{Action, Request, Response};
+    use wm::WindowManager;
+    use WorkspacesExt;
+
+    fn request(action: Action, target: Option<&str>) -> Request {
+        let mut request = Request::new(action);
+        request.target = target.map(String::from);
+        request
+    }
+
+    fn run(
+        wm: &mut MockWm,
+        history: &mut History,
+        action: Action,
+        target: Option<&str>,
+    ) -> Response {
+        handle_request(wm, history, &Config::default(), &request(action, target)).unwrap()
+    }
+
+    #[test]
+    fn go_to_existing_workspace_promotes_it() {
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["thunderbird"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+            ("3:web", "eDP-1", &["firefox"]),
+        ]);
+        let response = run(&mut wm, &mut History::new(), Action::GoTo, Some("web"));
+        assert_eq!(
+            response,
+            Response::Success {
+                focused: Some(String::from("1:web"))
+            }
+        );
+        assert_eq!(wm.names(), vec!["1:web", "2:mail", "3:rust"]);
+        assert_eq!(wm.windows("1:web"), vec!["firefox"]);
+    }
+
+    #[test]
+    fn go_to_new_title_creates_workspace() {
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["thunderbird"])]);
+        run(&mut wm, &mut History::new(), Action::GoTo, Some("notes"));
+        assert_eq!(wm.names(), vec!["1:notes", "2:mail"]);
+        assert_eq!(wm.focused(), "1:notes");
+        assert!(wm.windows("1:notes").is_empty());
+    }
+
+    #[test]
+    fn go_to_from_empty_workspace_closes_it() {
+        let mut wm = MockWm::new(&[("1:scratch", "eDP-1", &[]), ("2:mail", "eDP-1", &["mutt"])]);
+        run(&mut wm, &mut History::new(), Action::GoTo, Some("mail"));
+        assert_eq!(wm.names(), vec!["1:mail"]);
+    }
+
+    #[test]
+    fn send_to_moves_window_and_keeps_focus() {
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["thunderbird", "firefox"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+        ]);
+        run(&mut wm, &mut History::new(), Action::SendTo, Some("web"));
+        assert_eq!(wm.names(), vec!["1:mail", "2:rust", "3:web"]);
+        assert_eq!(wm.focused(), "1:mail");
+        assert_eq!(wm.windows("1:mail"), vec!["thunderbird"]);
+        assert_eq!(wm.windows("3:web"), vec!["firefox"]);
+    }
+
+    #[test]
+    fn bring_to_moves_window_and_follows_it() {
+        let mut wm = MockWm::new(&[
+            ("1:web", "eDP-1", &["firefox"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+        ]);
+        run(&mut wm, &mut History::new(), Action::BringTo, Some("rust"));
+        // the source workspace was left empty and got closed
+        assert_eq!(wm.names(), vec!["1:rust"]);
+        assert_eq!(wm.windows("1:rust"), vec!["emacs", "firefox"]);
+    }
+
+    #[test]
+    fn toggle_returns_to_previous_workspace() {
+        let config = Config::default();
+        let mut history = History::new();
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+        ]);
+        update_history(&mut wm, &mut history, &config, true).unwrap();
+
+        run(&mut wm, &mut history, Action::GoTo, Some("rust"));
+        update_history(&mut wm, &mut history, &config, true).unwrap();
+        assert_eq!(wm.focused(), "1:rust");
+
+        run(&mut wm, &mut history, Action::Toggle, None);
+        assert_eq!(wm.focused(), "1:mail");
+        assert_eq!(wm.names(), vec!["1:mail", "2:rust"]);
+    }
+
+    #[test]
+    fn toggle_can_stay_on_focused_output() {
+        let config = Config::default();
+        let mut history = History::new();
+        history.focus("rust", "eDP-1");
+        history.focus("web", "HDMI-1");
+        history.focus("mail", "eDP-1");
+
+        let mut wm = two_outputs();
+        let mut toggle = request(Action::Toggle, None);
+        toggle.flags.push(String::from("current_output"));
+        handle_request(&mut wm, &mut history, &config, &toggle).unwrap();
+        assert_eq!(wm.focused(), "1:rust");
+
+        // without the flag, the workspace focused last on any output wins
+        let mut wm = two_outputs();
+        run(&mut wm, &mut history, Action::Toggle, None);
+        assert_eq!(wm.focused(), "3:web");
+    }
+
+    #[test]
+    fn toggle_without_history_fails() {
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+        let request = request(Action::Toggle, None);
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn blank_target_is_rejected() {
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+        let request = request(Action::GoTo, Some("  "));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn custom_separator_is_used_for_new_names() {
+        let config = Config {
+            separator: String::from(" | "),
+            ..Config::default()
+        };
+        let mut wm = MockWm::new(&[("1 | mail", "eDP-1", &["mutt"])]);
+        let request = request(Action::GoTo, Some("web"));
+        handle_request(&mut wm, &mut History::new(), &config, &request).unwrap();
+        assert_eq!(wm.names(), vec!["1 | web", "2 | mail"]);
+    }
+
+    #[test]
+    fn hostile_titles_stay_inside_their_quotes() {
+        for title in &[
+            "foo\"; exec rm -rf ~",
+            "back\\slash",
+            "trailing\\",
+            "a, b; c",
+        ] {
+            let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+            let mut history = History::new();
+            run(&mut wm, &mut history, Action::BringTo, Some(title));
+            let name = format!("1:{}", title);
+            assert_eq!(wm.names(), vec![name.as_str()]);
+            assert_eq!(wm.windows(&name), vec!["mutt"]);
+
+            // the workspace can be found and renumbered again by its title
+            run(&mut wm, &mut history, Action::GoTo, Some("mail"));
+            run(&mut wm, &mut history, Action::GoTo, Some(title));
+            assert_eq!(wm.names(), vec![name.as_str()]);
+        }
+    }
+
+    #[test]
+    fn rename_keeps_number_and_history() {
+        let config = Config::default();
+        let mut history = History::new();
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+            ("3:web", "eDP-1", &["firefox"]),
+        ]);
+        run(&mut wm, &mut history, Action::GoTo, Some("rust"));
+        update_history(&mut wm, &mut history, &config, true).unwrap();
+        run(&mut wm, &mut history, Action::GoTo, Some("mail"));
+        update_history(&mut wm, &mut history, &config, true).unwrap();
+
+        let response = run(&mut wm, &mut history, Action::Rename, Some("inbox"));
+        assert_eq!(
+            response,
+            Response::Success {
+                focused: Some(String::from("1:inbox"))
+            }
+        );
+        assert_eq!(wm.names(), vec!["1:inbox", "2:rust", "3:web"]);
+        assert_eq!(wm.windows("1:inbox"), vec!["mutt"]);
+        assert_eq!(history.last_used()[0], "inbox");
+        assert_eq!(history.times_chosen("inbox"), 1);
+    }
+
+    #[test]
+    fn rename_to_existing_title_is_rejected() {
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:web", "eDP-1", &["firefox"]),
+        ]);
+        let request = request(Action::Rename, Some("web"));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn rename_to_same_title_does_nothing() {
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+        run(&mut wm, &mut History::new(), Action::Rename, Some("mail"));
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn merge_moves_every_window_and_closes_source() {
+        let mut wm = MockWm::new(&[
+            ("1:web", "eDP-1", &["firefox", "chromium"]),
+            ("2:mail", "eDP-1", &["mutt"]),
+            ("3:rust", "eDP-1", &["emacs"]),
+        ]);
+        run(&mut wm, &mut History::new(), Action::Merge, Some("rust"));
+        assert_eq!(wm.names(), vec!["1:rust", "2:mail"]);
+        assert_eq!(wm.windows("1:rust"), vec!["emacs", "firefox", "chromium"]);
+    }
+
+    #[test]
+    fn merge_from_named_source_keeps_focus() {
+        let mut wm = MockWm::new(&[
+            ("1:web", "eDP-1", &["firefox"]),
+            ("2:mail", "eDP-1", &["mutt"]),
+            ("3:inbox", "eDP-1", &["thunderbird"]),
+        ]);
+        let mut request = request(Action::Merge, Some("mail"));
+        request.source = Some(String::from("inbox"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:web", "2:mail"]);
+        assert_eq!(wm.focused(), "1:web");
+        assert_eq!(wm.windows("2:mail"), vec!["mutt", "thunderbird"]);
+    }
+
+    #[test]
+    fn merge_into_itself_is_rejected() {
+        let mut wm = MockWm::new(&[("1:web", "eDP-1", &["firefox"])]);
+        let request = request(Action::Merge, Some("web"));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+
+        let mut request = request.clone();
+        request.source = Some(String::from("nowhere"));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        assert!(wm.commands.is_empty());
+    }
+
+    fn four_workspaces() -> MockWm {
+        MockWm::new(&[
+            ("1:a", "eDP-1", &["w1"]),
+            ("2:b", "eDP-1", &["w2"]),
+            ("3:c", "eDP-1", &["w3"]),
+            ("4:d", "eDP-1", &["w4"]),
+        ])
+    }
+
+    #[test]
+    fn move_up_and_down_swap_neighbours() {
+        let mut wm = four_workspaces();
+        run(&mut wm, &mut History::new(), Action::MoveDown, None);
+        assert_eq!(wm.names(), vec!["1:b", "2:a", "3:c", "4:d"]);
+        assert_eq!(wm.focused(), "2:a");
+
+        let mut request = request(Action::MoveUp, Some("d"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:b", "2:a", "3:d", "4:c"]);
+
+        // the first workspace stays where it is
+        request.target = Some(String::from("b"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:b", "2:a", "3:d", "4:c"]);
+    }
+
+    #[test]
+    fn move_to_position() {
+        let mut wm = four_workspaces();
+        let mut request = request(Action::MoveTo, None);
+        request.position = Some(3);
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:b", "2:c", "3:a", "4:d"]);
+
+        // numbers past the end move it to the end
+        request.position = Some(2_000_000_000);
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:b", "2:c", "3:d", "4:a"]);
+
+        request.position = None;
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        request.position = Some(0);
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+        request.position = Some(usize::MAX);
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+    }
+
+    #[test]
+    fn pinned_workspaces_keep_their_numbers() {
+        let mut config = Config::default();
+        config.pins.insert(String::from("b"), 2);
+        let mut history = History::new();
+        let mut wm = four_workspaces();
+
+        // going to another workspace promotes it past the pinned one
+        let go_to_d = request(Action::GoTo, Some("d"));
+        handle_request(&mut wm, &mut history, &config, &go_to_d).unwrap();
+        assert_eq!(wm.names(), vec!["1:d", "2:b", "3:a", "4:c"]);
+
+        // pinned workspaces can't be moved, and their numbers can't be taken
+        let move_b = request(Action::MoveDown, Some("b"));
+        assert!(handle_request(&mut wm, &mut history, &config, &move_b).is_err());
+        let mut move_to_2 = request(Action::MoveTo, None);
+        move_to_2.position = Some(2);
+        assert!(handle_request(&mut wm, &mut history, &config, &move_to_2).is_err());
+
+        // moving to number 3 skips the pinned number
+        let mut move_to_3 = request(Action::MoveTo, Some("c"));
+        move_to_3.position = Some(3);
+        handle_request(&mut wm, &mut history, &config, &move_to_3).unwrap();
+        assert_eq!(wm.names(), vec!["1:d", "2:b", "3:c", "4:a"]);
+    }
+
+    #[test]
+    fn pinned_workspace_is_created_with_its_number() {
+        let mut config = Config::default();
+        config.pins.insert(String::from("mail"), 5);
+        let mut wm = MockWm::new(&[("1:web", "eDP-1", &["firefox"])]);
+        let request = request(Action::SendTo, Some("mail"));
+        handle_request(&mut wm, &mut History::new(), &config, &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:web", "5:mail"]);
+    }
+
+    #[test]
+    fn pinned_workspaces_are_not_promoted() {
+        let mut config = Config::default();
+        config.pins.insert(String::from("mail"), 1);
+        config.pins.insert(String::from("chat"), 2);
+        let mut history = History::new();
+        let mut wm = MockWm::new(&[
+            ("3:web", "eDP-1", &["firefox"]),
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:chat", "eDP-1", &["irssi"]),
+            ("4:rust", "eDP-1", &["emacs"]),
+        ]);
+
+        let go_to_mail = request(Action::GoTo, Some("mail"));
+        handle_request(&mut wm, &mut history, &config, &go_to_mail).unwrap();
+        assert_eq!(wm.focused(), "1:mail");
+        assert_eq!(wm.commands, vec!["workspace \"1:mail\""]);
+
+        // the dynamic workspaces keep floating around the pinned ones
+        let go_to_rust = request(Action::GoTo, Some("rust"));
+        handle_request(&mut wm, &mut history, &config, &go_to_rust).unwrap();
+        assert_eq!(wm.names(), vec!["1:mail", "2:chat", "3:rust", "4:web"]);
+    }
+
+    fn two_outputs() -> MockWm {
+        MockWm::new(&[
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:web", "HDMI-1", &["firefox"]),
+            ("3:rust", "eDP-1", &["emacs"]),
+        ])
+    }
+
+    #[test]
+    fn menu_can_be_limited_to_focused_output_and_annotated() {
+        let config = Config::default();
+        let history = History::new();
+        let workspaces = two_outputs().get_workspaces().unwrap();
+        let mut request = request(Action::GoTo, None);
+
+        request.flags = vec![String::from("current_output")];
+        let entries = menu_entries(&workspaces, &history, &config, &request);
+        assert_eq!(
+            entries,
+            vec![
+                (String::from("mail"), "mail"),
+                (String::from("rust"), "rust")
+            ]
+        );
+
+        request.flags = vec![String::from("show_outputs")];
+        let entries = menu_entries(&workspaces, &history, &config, &request);
+        assert_eq!(entries.len(), 3);
+        assert!(entries.contains(&(String::from("web (HDMI-1)"), "web")));
+    }
+
+    #[test]
+    fn new_workspaces_are_created_on_requested_output() {
+        let mut wm = two_outputs();
+        let mut go_to_notes = request(Action::GoTo, Some("notes"));
+        go_to_notes.output = Some(String::from("HDMI-1"));
+        handle_request(
+            &mut wm,
+            &mut History::new(),
+            &Config::default(),
+            &go_to_notes,
+        )
+        .unwrap();
+        assert_eq!(wm.names(), vec!["1:mail", "2:rust", "3:notes", "4:web"]);
+        assert_eq!(wm.focused(), "3:notes");
+        assert_eq!(wm.output("3:notes"), "HDMI-1");
+
+        let mut wm = two_outputs();
+        let mut send_to_chat = request(Action::SendTo, Some("chat"));
+        send_to_chat.output = Some(String::from("HDMI-1"));
+        handle_request(
+            &mut wm,
+            &mut History::new(),
+            &Config::default(),
+            &send_to_chat,
+        )
+        .unwrap();
+        assert_eq!(wm.focused(), "1:mail");
+        assert_eq!(wm.output("4:chat"), "HDMI-1");
+        assert_eq!(wm.windows("4:chat"), vec!["mutt"]);
+    }
+
+    #[test]
+    fn existing_workspaces_stay_on_their_output() {
+        let mut wm = two_outputs();
+        let mut request = request(Action::GoTo, Some("rust"));
+        request.output = Some(String::from("HDMI-1"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.output("1:rust"), "eDP-1");
+
+        request.output = Some(String::from("DP-3"));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+    }
+
+    #[test]
+    fn move_to_output_moves_workspace_and_renumbers() {
+        let mut wm = two_outputs();
+        let mut request = request(Action::MoveToOutput, None);
+        request.output = Some(String::from("HDMI-1"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:rust", "2:mail", "3:web"]);
+        assert_eq!(wm.output("2:mail"), "HDMI-1");
+        assert_eq!(wm.focused(), "2:mail");
+
+        request.target = Some(String::from("web"));
+        request.output = Some(String::from("eDP-1"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.names(), vec!["1:rust", "2:web", "3:mail"]);
+        assert_eq!(wm.output("2:web"), "eDP-1");
+        assert_eq!(wm.focused(), "3:mail");
+    }
+
+    #[test]
+    fn move_to_unknown_output_is_rejected() {
+        let mut wm = two_outputs();
+        let mut request = request(Action::MoveToOutput, None);
+        request.output = Some(String::from("DP-3"));
+        assert!(
+            handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+        );
+
+        // moving a workspace to the output it's on does nothing
+        request.output = Some(String::from("eDP-1"));
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn per_output_numbering_keeps_outputs_apart() {
+        let config = Config {
+            numbering: NumberingMode::PerOutput,
+            output_step: 10,
+            ..Config::default()
+        };
+        let mut history = History::new();
+        let mut wm = two_outputs();
+
+        let go_to_rust = request(Action::GoTo, Some("rust"));
+        handle_request(&mut wm, &mut history, &config, &go_to_rust).unwrap();
+        assert_eq!(wm.names(), vec!["1:rust", "2:mail", "11:web"]);
+
+        // moving down stops at the end of the output
+        let move_down = request(Action::MoveDown, None);
+        handle_request(&mut wm, &mut history, &config, &move_down).unwrap();
+        handle_request(&mut wm, &mut history, &config, &move_down).unwrap();
+        assert_eq!(wm.names(), vec!["1:mail", "2:rust", "11:web"]);
+
+        // new workspaces get numbers from the range of their output
+        let mut send_to_chat = request(Action::SendTo, Some("chat"));
+        send_to_chat.output = Some(String::from("HDMI-1"));
+        handle_request(&mut wm, &mut history, &config, &send_to_chat).unwrap();
+        assert_eq!(wm.names(), vec!["1:rust", "2:mail", "11:web", "12:chat"]);
+
+        let mut move_to_1 = request(Action::MoveTo, Some("chat"));
+        move_to_1.position = Some(1);
+        assert!(handle_request(&mut wm, &mut history, &config, &move_to_1).is_err());
+        move_to_1.position = Some(11);
+        handle_request(&mut wm, &mut history, &config, &move_to_1).unwrap();
+        assert_eq!(wm.names(), vec!["1:rust", "2:mail", "11:chat", "12:web"]);
+    }
+
+    #[test]
+    fn index_actions_target_workspace_by_number_without_promoting() {
+        let mut wm = four_workspaces();
+        let mut request = request(Action::GoToIndex, None);
+        request.position = Some(3);
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.focused(), "3:c");
+        assert_eq!(wm.names(), vec!["1:a", "2:b", "3:c", "4:d"]);
+
+        request.action = Action::BringToIndex;
+        request.position = Some(1);
+        handle_request(&mut wm, &mut History::new(), &Config::default(), &request).unwrap();
+        assert_eq!(wm.focused(), "1:a");
+        assert_eq!(wm.windows("1:a"), vec!["w1", "w3"]);
+        assert_eq!(wm.names(), vec!["1:a", "2:b", "3:d"]);
+
+        // numbers i3 can't have are refused rather than wrapped around onto small ones
+        for &position in &[9, 4_294_967_298, usize::MAX] {
+            request.position = Some(position);
+            assert!(
+                handle_request(&mut wm, &mut History::new(), &Config::default(), &request).is_err()
+            );
+        }
+        assert_eq!(wm.focused(), "1:a");
+    }
+
+    #[test]
+    fn index_actions_count_on_the_focused_output() {
+        let config = Config {
+            numbering: NumberingMode::PerOutput,
+            output_step: 10,
+            ..Config::default()
+        };
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["mutt"]),
+            ("2:rust", "eDP-1", &["emacs"]),
+            ("11:web", "HDMI-1", &["firefox"]),
+            ("12:chat", "HDMI-1", &["irssi"]),
+        ]);
+        let mut request = request(Action::SendToIndex, None);
+        request.position = Some(2);
+        handle_request(&mut wm, &mut History::new(), &config, &request).unwrap();
+        assert_eq!(wm.windows("2:rust"), vec!["emacs", "mutt"]);
+
+        request.output = Some(String::from("HDMI-1"));
+        request.action = Action::GoToIndex;
+        handle_request(&mut wm, &mut History::new(), &config, &request).unwrap();
+        assert_eq!(wm.focused(), "12:chat");
+
+        request.position = Some(usize::MAX);
+        assert!(handle_request(&mut wm, &mut History::new(), &config, &request).is_err());
+        let workspaces = wm.get_workspaces().unwrap();
+        assert!(workspaces
+            .get_wp_at_position(usize::MAX, ":", &config.pins, config.numbering(), "HDMI-1")
+            .is_none());
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around_without_promoting() {
+        let mut history = History::new();
+        let mut wm = four_workspaces();
+        run(&mut wm, &mut history, Action::Prev, None);
+        assert_eq!(wm.focused(), "4:d");
+        run(&mut wm, &mut history, Action::Next, None);
+        run(&mut wm, &mut history, Action::Next, None);
+        assert_eq!(wm.focused(), "2:b");
+        assert_eq!(wm.names(), vec!["1:a", "2:b", "3:c", "4:d"]);
+    }
+
+    #[test]
+    fn next_goes_through_last_used_order_from_start_of_pass() {
+        let config = Config {
+            order: Order::LastUsed,
+            ..Config::default()
+        };
+        let mut history = History::new();
+        let mut wm = four_workspaces();
+        for position in &[3, 2, 4, 1] {
+            let mut go_to = request(Action::GoToIndex, None);
+            go_to.position = Some(*position);
+            handle_request(&mut wm, &mut history, &config, &go_to).unwrap();
+            update_history(&mut wm, &mut history, &config, true).unwrap();
+        }
+
+        // each step is recorded as a focus change, but the pass keeps its order
+        let mut visited = vec![];
+        for _ in 0..4 {
+            handle_request(&mut wm, &mut history, &config, &request(Action::Next, None)).unwrap();
+            update_history(&mut wm, &mut history, &config, true).unwrap();
+            visited.push(wm.focused().to_owned());
+        }
+        assert_eq!(visited, vec!["4:d", "2:b", "3:c", "1:a"]);
+    }
+
+    #[test]
+    fn templates_fill_new_workspaces() {
+        let config = Config::parse(
+            r#"
+            [[templates]]
+            pattern = "rust*"
+            layout = "~/.config/i3/rust.json"
+            exec = ["alacritty", "emacs"]
+            "#,
+        )
+        .unwrap();
+        let mut history = History::new();
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+
+        let go_to_rust = request(Action::GoTo, Some("rust"));
+        handle_request(&mut wm, &mut history, &config, &go_to_rust).unwrap();
+        assert_eq!(wm.windows("1:rust"), vec!["alacritty", "emacs"]);
+        assert!(wm
+            .commands
+            .contains(&String::from("append_layout \"~/.config/i3/rust.json\"")));
+
+        // existing workspaces, and ones that are only sent windows, are left alone
+        handle_request(&mut wm, &mut history, &config, &go_to_rust).unwrap();
+        let send_to_rusty = request(Action::SendTo, Some("rusty"));
+        handle_request(&mut wm, &mut history, &config, &send_to_rusty).unwrap();
+        assert_eq!(wm.windows("1:rust"), vec!["alacritty"]);
+        assert_eq!(wm.windows("3:rusty"), vec!["emacs"]);
+    }
+
+    #[test]
+    fn restore_moves_windows_back_to_their_workspaces() {
+        let config = Config::default();
+        let mut before = MockWm::new(&[
+            ("1:web", "eDP-1", &["firefox"]),
+            ("2:mail", "eDP-1", &["thunderbird"]),
+            ("3:code", "HDMI-1", &["emacs", "alacritty"]),
+        ]);
+        let session = snapshot(&mut before, &config).unwrap();
+
+        // after logging in again, the same programs are open on bare-numbered workspaces
+        let mut wm = MockWm::new(&[
+            ("1", "eDP-1", &["alacritty", "firefox", "xterm"]),
+            ("2", "HDMI-1", &["emacs", "thunderbird"]),
+        ]);
+        restore(&mut wm, &config, Some(&session)).unwrap();
+        assert_eq!(wm.names(), vec!["1", "2:web", "3:mail", "4:code"]);
+        assert_eq!(wm.windows("1"), vec!["xterm"]);
+        assert_eq!(wm.windows("2:web"), vec!["firefox"]);
+        assert_eq!(wm.windows("3:mail"), vec!["thunderbird"]);
+        assert_eq!(wm.windows("4:code"), vec!["alacritty", "emacs"]);
+        assert_eq!(wm.output("4:code"), "HDMI-1");
+
+        assert!(restore(&mut wm, &config, None).is_err());
+    }
+
+    #[test]
+    fn new_windows_are_routed_by_rules() {
+        let config = Config::parse(
+            "[pins]\nchat = 1\n\n[[rules]]\nclass = \"thunder*\"\nworkspace = \"mail\"\n\n\
+             [[rules]]\nclass = \"slack\"\nworkspace = \"chat\"",
+        )
+        .unwrap();
+        let mut wm = MockWm::new(&[
+            ("2:web", "eDP-1", &["firefox", "thunderbird", "slack"]),
+            ("3:mail", "eDP-1", &["mutt"]),
+        ]);
+        let id = |wm: &mut MockWm, name: &str| {
+            wm.get_windows()
+                .unwrap()
+                .into_iter()
+                .find(|w| w.title.as_deref() == Some(name))
+                .unwrap()
+                .id
+        };
+
+        let thunderbird = id(&mut wm, "thunderbird");
+        route_window(&mut wm, &config, thunderbird).unwrap();
+        assert_eq!(wm.windows("3:mail"), vec!["mutt", "thunderbird"]);
+
+        // a missing workspace is created with its pinned number, without taking focus
+        let slack = id(&mut wm, "slack");
+        route_window(&mut wm, &config, slack).unwrap();
+        assert_eq!(wm.names(), vec!["1:chat", "2:web", "3:mail"]);
+        assert_eq!(wm.windows("1:chat"), vec!["slack"]);
+        assert_eq!(wm.focused(), "2:web");
+
+        // windows without a rule, and windows that are gone, are left alone
+        let firefox = id(&mut wm, "firefox");
+        route_window(&mut wm, &config, firefox).unwrap();
+        route_window(&mut wm, &config, 1000).unwrap();
+        assert_eq!(wm.windows("2:web"), vec!["firefox"]);
+    }
+
+    #[test]
+    fn bare_workspaces_are_titled_after_their_windows() {
+        let config = Config::parse("auto_titles = true").unwrap();
+        let mut wm = MockWm::new(&[
+            ("1", "eDP-1", &["Firefox"]),
+            ("2", "eDP-1", &["Alacritty", "emacs", "Alacritty"]),
+            ("3:web", "eDP-1", &["firefox"]),
+            ("4", "eDP-1", &["firefox"]),
+        ]);
+        let mut history = History::new();
+        let mut auto_titled = Default::default();
+        let mut update = |wm: &mut MockWm| {
+            auto_title(wm, &mut history, &config, &mut auto_titled).unwrap();
+        };
+
+        update(&mut wm);
+        assert_eq!(
+            wm.names(),
+            vec!["1:firefox", "2:alacritty+emacs", "3:web", "4:firefox-2"]
+        );
+
+        // titles follow the windows, and the focused workspace goes back to its number once empty
+        let firefox = wm.get_windows().unwrap()[0].id;
+        wm.run_commands(&[Command::MoveWindowToWorkspace {
+            id: firefox,
+            workspace: String::from("2:alacritty+emacs"),
+        }])
+        .unwrap();
+        update(&mut wm);
+        assert_eq!(
+            wm.names(),
+            vec!["1", "2:alacritty+emacs+firefox", "3:web", "4:firefox"]
+        );
+
+        // titles the user chose are left alone
+        wm.run_commands(&[Command::RenameWorkspace {
+            from: String::from("2:alacritty+emacs+firefox"),
+            to: String::from("2:code"),
+        }])
+        .unwrap();
+        update(&mut wm);
+        assert_eq!(wm.names(), vec!["1", "2:code", "3:web", "4:firefox"]);
+    }
+
+    #[test]
+    fn workspaces_created_by_i3_are_titled_after_fixup() {
+        let config = Config::parse("auto_titles = true").unwrap();
+        let mut wm = MockWm::new(&[
+            ("1:mail", "eDP-1", &["thunderbird"]),
+            ("2:web", "eDP-1", &["firefox"]),
+            ("5", "eDP-1", &["alacritty"]),
+        ]);
+
+        // the init event renumbers the new workspace before its window event arrives
+        fixup(&mut wm, &config).unwrap();
+        assert_eq!(wm.names(), vec!["1:mail", "2:web", "3"]);
+
+        auto_title(
+            &mut wm,
+            &mut History::new(),
+            &config,
+            &mut Default::default(),
+        )
+        .unwrap();
+        assert_eq!(wm.names(), vec!["1:mail", "2:web", "3:alacritty"]);
+    }
+
+    #[test]
+    fn daemon_actions_are_refused() {
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["thunderbird"])]);
+        for &action in &[Action::Reload, Action::Restore] {
+            assert!(handle_request(
+                &mut wm,
+                &mut History::new(),
+                &Config::default(),
+                &request(action, None)
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn numeric_titles_are_kept() {
+        let config = Config::parse("auto_titles = true").unwrap();
+        let mut history = History::new();
+        let mut wm = MockWm::new(&[("1:mail", "eDP-1", &["mutt"])]);
+
+        let go_to = request(Action::GoTo, Some("2048"));
+        handle_request(&mut wm, &mut history, &config, &go_to).unwrap();
+        assert_eq!(wm.focused(), "1:2048");
+        handle_request(&mut wm, &mut history, &config, &go_to).unwrap();
+        assert_eq!(wm.names(), vec!["1:2048", "2:mail"]);
+
+        let rename = request(Action::Rename, Some("42"));
+        handle_request(&mut wm, &mut history, &config, &rename).unwrap();
+        fixup(&mut wm, &config).unwrap();
+        assert_eq!(wm.focused(), "1:42");
+
+        // numbers the user chose aren't mistaken for missing titles
+        wm.run_commands(&[Command::Exec(String::from("firefox"))])
+            .unwrap();
+        auto_title(&mut wm, &mut history, &config, &mut Default::default()).unwrap();
+        assert_eq!(wm.names(), vec!["1:42", "2:mail"]);
+    }
+}
@@ -1,12 +1,33 @@
 extern crate clap;
+extern crate failure;
+extern crate fluidspaces;
 
 use clap::App;
 use clap::Arg;
 
+use failure::err_msg;
+use failure::Error;
+
+use fluidspaces::history::ORDER_NAMES;
+use fluidspaces::menu::MENU_NAMES;
+use fluidspaces::protocol::Action;
+use fluidspaces::protocol::Request;
+use fluidspaces::protocol::Response;
+use fluidspaces::protocol::ACTION_NAMES;
+use fluidspaces::protocol::FLAG_NAMES;
+use fluidspaces::socket::default_socket_path;
+
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Write;
 
 use std::os::unix::net::UnixStream;
 
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::process;
+
 fn main() {
     let matches = App::new("fluidspaces")
         .version("0.5.1")
@@ -27,44 +48,136 @@ fn main() {
         //     .short("-t")
         //     .long("--toggle")
         //     .help("Skip menu & choose workspace 2 (default: false)"))
-        // .arg(Arg::with_name("order")
-        //     .short("-o")
-        //     .long("--order")
-        //     .possible_values(&["default", "last-used"])
-        //     .default_value("default")
-        //     .help("Method used to determine workspace display order"))
+        .arg(Arg::with_name("order")
+            .short("-o")
+            .long("--order")
+            .takes_value(true)
+            .possible_values(ORDER_NAMES)
+            .help("Method used to determine workspace display order (default: the daemon's --order)"))
         .arg(Arg::with_name("action")
             .short("-a")
             .long("--action")
-            .possible_values(&["go_to", "send_to", "bring_to", "toggle"])
+            .possible_values(ACTION_NAMES)
             .default_value("go_to")
             .help("Action to perform"))
-        // .arg(Arg::with_name("menu")
-        //     .short("-m")
-        //     .long("--menu")
-        //     .possible_values(&["dmenu", "rofi"])
-        //     .default_value("dmenu")
-        //     .help("Program used to render the menu"))
+        .arg(Arg::with_name("target")
+            .short("-t")
+            .long("--target")
+            .takes_value(true)
+            .help("Title of the workspace to act on (the new title, for rename), instead of picking one from the menu"))
+        .arg(Arg::with_name("position")
+            .short("-p")
+            .long("--position")
+            .takes_value(true)
+            .help("Workspace number to move the workspace to, for move_to, or of the workspace to act on, for go_to_index, send_to_index and bring_to_index"))
+        .arg(Arg::with_name("source")
+            .long("--source")
+            .takes_value(true)
+            .help("Title of the workspace to merge (default: the focused one)"))
+        .arg(Arg::with_name("output")
+            .long("--output")
+            .takes_value(true)
+            .help("Output (monitor) to create new workspaces on (default: the focused one), or to move the workspace to for move_to_output"))
+        .arg(Arg::with_name("current_output")
+            .long("--current-output")
+            .help("Only offer the workspaces on the focused output in the menu, step through them with next and prev, or toggle between them"))
+        .arg(Arg::with_name("show_outputs")
+            .long("--show-outputs")
+            .help("Show the output of each workspace in the menu"))
+        .arg(Arg::with_name("menu")
+            .short("-m")
+            .long("--menu")
+            .takes_value(true)
+            .possible_values(MENU_NAMES)
+            .help("Program used to render the menu (default: the daemon's menu setting, dmenu if it isn't set)"))
+        .arg(Arg::with_name("socket")
+            .short("-s")
+            .long("--socket")
+            .takes_value(true)
+            .help("Path of the daemon's socket (default: fluidspaces-$WAYLAND_DISPLAY.sock or fluidspaces-$DISPLAY.sock in $XDG_RUNTIME_DIR)"))
         .get_matches();
 
     // ------------------------------------------------
 
     // define filename for fluidspaces IPC socket
-    let socket_filename = "/tmp/fluidspaces.sock";
+    let socket_filename = match matches.value_of("socket") {
+        Some(path) => PathBuf::from(path),
+        None => default_socket_path(),
+    };
+
+    // build a request from the values of the command line options
+    let action: Action = matches
+        .value_of("action")
+        .unwrap_or("go_to")
+        .parse()
+        .unwrap();
+    let mut request = Request::new(action);
+    request.target = matches.value_of("target").map(String::from);
+    request.source = matches.value_of("source").map(String::from);
+    request.position = match matches.value_of("position").map(|p| p.parse()) {
+        Some(Ok(position)) => Some(position),
+        Some(Err(_)) => {
+            eprintln!("fluidspaces: The position must be a workspace number");
+            process::exit(1);
+        }
+        None => None,
+    };
+    request.menu = matches.value_of("menu").map(String::from);
+    request.order = matches.value_of("order").map(|o| o.parse().unwrap());
+    request.output = matches.value_of("output").map(String::from);
+    for flag in FLAG_NAMES {
+        if matches.is_present(flag) {
+            request.flags.push(String::from(*flag));
+        }
+    }
 
+    // send the request to the daemon and wait for its reply; failing to talk to the daemon at
+    // all is reported the same way as an error reported by the daemon
+    let response = match send_request(&socket_filename, &request) {
+        Ok(response) => response,
+        Err(e) => Response::Error {
+            message: e.to_string(),
+        },
+    };
+
+    // report the outcome to the user
+    match response {
+        Response::Success { ref focused } => {
+            if let Some(name) = focused {
+                println!("{}", name);
+            }
+        }
+        Response::Cancelled => (),
+        Response::Error { ref message } => eprintln!("fluidspaces: {}", message),
+    }
+
+    process::exit(response.exit_code());
+}
+
+fn send_request(socket_filename: &Path, request: &Request) -> Result<Response, Error> {
     // connect to the socket
     let mut stream = match UnixStream::connect(socket_filename) {
         Ok(sock) => sock,
-        Err(e) => panic!("Couldn't connect to socket: {:?}", e),
+        Err(e) => {
+            return Err(err_msg(format!(
+                "Couldn't connect to socket {:?}: {}",
+                socket_filename, e
+            )))
+        }
     };
 
     // set stream behavior to "blocking"
-    if let Err(e) = stream.set_nonblocking(false) {
-        panic!("Couldn't set socket to blocking mode: {:?}", e);
+    stream.set_nonblocking(false)?;
+
+    // send the request to the socket
+    stream.write_all(request.to_line()?.as_bytes())?;
+
+    // wait for the daemon to finish handling the request and read its reply
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    if reply.is_empty() {
+        return Err(err_msg("The daemon closed the connection without replying"));
     }
 
-    // send the value of the action option to the socket
-    stream
-        .write_all(matches.value_of("action").unwrap_or("go_to").as_bytes())
-        .unwrap();
+    Response::from_line(&reply)
 }
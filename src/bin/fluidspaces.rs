@@ -1,229 +1,407 @@
 extern crate clap;
 extern crate failure;
 extern crate fluidspaces;
-extern crate i3ipc;
+extern crate signal_hook;
 
 // use clap::{Arg, ArgGroup, App};
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
 
-use failure::err_msg;
 use failure::Error;
 
-use i3ipc::I3Connection;
 // use i3ipc::reply::{Workspace, Workspaces};
 
+use signal_hook::iterator::Signals;
+
 use std::fs;
 
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Write;
 // use std::io::ErrorKind;
 // use std::io::Error;
-use std::io::Read;
 
 // use std::net::Shutdown;
 
+use std::path::PathBuf;
+
+use std::process;
+
+use std::sync::mpsc;
+use std::thread;
+
 use std::os::unix::net::UnixListener;
 use std::os::unix::net::UnixStream;
 // use std::os::unix::net::UnixDatagram;
 
-use std::process::Command;
-use std::process::Stdio;
-
-use fluidspaces::go_to;
-use fluidspaces::send_to;
-use fluidspaces::I3ConnectionExt;
-use fluidspaces::WorkspaceExt;
-use fluidspaces::WorkspacesExt;
+use fluidspaces::actions::auto_title;
+use fluidspaces::actions::fixup;
+use fluidspaces::actions::handle_request;
+use fluidspaces::actions::restore;
+use fluidspaces::actions::route_window;
+use fluidspaces::actions::snapshot;
+use fluidspaces::actions::update_history;
+use fluidspaces::config::Config;
+use fluidspaces::history::History;
+use fluidspaces::history::ORDER_NAMES;
+use fluidspaces::protocol::Action;
+use fluidspaces::protocol::Request;
+use fluidspaces::protocol::Response;
+use fluidspaces::session::Session;
+use fluidspaces::socket::default_socket_path;
+use fluidspaces::socket::prepare_socket_dir;
+use fluidspaces::socket::restrict_socket;
+use fluidspaces::wm;
+use fluidspaces::wm::WindowChange;
+use fluidspaces::wm::WindowManager;
+use fluidspaces::wm::WmEvent;
+use fluidspaces::wm::WorkspaceChange;
+use fluidspaces::wm::WM_NAMES;
+use fluidspaces::AutoTitles;
 
 // use fluidspaces::parse_title_from_name;
 
 fn main() {
-    // establish connection with i3 IPC socket
-    let mut i3 = match I3Connection::connect() {
-        Ok(connection) => connection,
-        Err(e) => panic!("Couldn't connect to i3: {:?}", e),
+    let matches = App::new("fluidspaces")
+        .version("0.5.1")
+        .author("Peter Henry <me@peterhenry.net>")
+        .about("Daemon that manages dynamically named i3 and sway workspaces; controlled with fluidspaces-msg.")
+        .arg(Arg::with_name("config")
+            .short("-c")
+            .long("--config")
+            .takes_value(true)
+            .help("Path of the config file (default: $XDG_CONFIG_HOME/fluidspaces/config.toml)"))
+        .arg(Arg::with_name("socket")
+            .short("-s")
+            .long("--socket")
+            .takes_value(true)
+            .help("Path of the socket to listen on (default: fluidspaces-$WAYLAND_DISPLAY.sock or fluidspaces-$DISPLAY.sock in $XDG_RUNTIME_DIR)"))
+        .arg(Arg::with_name("wm")
+            .short("-w")
+            .long("--wm")
+            .takes_value(true)
+            .possible_values(WM_NAMES)
+            .help("Window manager to connect to (default: sway if $SWAYSOCK is set, i3 otherwise)"))
+        .arg(Arg::with_name("order")
+            .short("-o")
+            .long("--order")
+            .takes_value(true)
+            .possible_values(ORDER_NAMES)
+            .help("Workspace display order used when a request doesn't specify one (default: from the config file)"))
+        .get_matches();
+
+    // ------------------------------------------------
+
+    // read the config file; a broken config is reported and the daemon refuses to start
+    let mut config = match load_config(&matches) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // establish connection with the window manager's IPC socket
+    let mut wm = match wm::connect(config.wm) {
+        Ok(wm) => wm,
+        Err(e) => panic!("Couldn't connect to the window manager: {}", e),
     };
 
     // define filename for fluidspaces IPC socket
-    let socket_filename = "/tmp/fluidspaces.sock";
+    let socket_filename = config.socket.clone().unwrap_or_else(default_socket_path);
+
+    // make sure the socket's directory exists and is private
+    if let Err(e) = prepare_socket_dir(&socket_filename) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 
     // try to delete old socket file; ignore success and failure
-    match fs::remove_file(socket_filename) {
-        _ => (),
-    };
+    let _ = fs::remove_file(&socket_filename);
 
     // bind listener to a new socket
-    let listener = match UnixListener::bind(socket_filename) {
+    let listener = match UnixListener::bind(&socket_filename) {
         Ok(sock) => sock,
-        Err(e) => panic!("Couldn't bind to new socket: {:?}", e),
+        Err(e) => panic!("Couldn't bind to new socket {:?}: {:?}", socket_filename, e),
     };
 
+    // only the current user may talk to the daemon
+    if let Err(e) = restrict_socket(&socket_filename) {
+        panic!(
+            "Couldn't restrict permissions of socket {:?}: {}",
+            socket_filename, e
+        );
+    }
+
+    println!("listening on {:?}", socket_filename);
+
     // set listener behavior to "blocking"
     if let Err(e) = listener.set_nonblocking(false) {
         panic!("Couldn't set socket listener to blocking mode: {:?}", e);
     }
 
-    // start event loop - blocks until the socket receives a message
-    for stream_res in listener.incoming() {
-        match stream_res {
-            // if the stream was successfully read from the socket
-            Ok(mut stream) => {
-                println!("----------"); // DEBUG
-
-                // process the stream
-                if let Err(e) = handle_stream(&mut i3, &mut stream) {
-                    eprintln!("{}", e.cause());
+    // requests from clients and events from the window manager are funnelled into a single channel so that they
+    // are handled one at a time, in the order they arrived
+    let (tx, rx) = mpsc::channel();
+
+    // accept client connections on a separate thread - blocks until the socket receives a message
+    let client_tx = tx.clone();
+    thread::spawn(move || {
+        for stream_res in listener.incoming() {
+            match stream_res {
+                // if the stream was successfully read from the socket
+                Ok(stream) => {
+                    if client_tx.send(DaemonEvent::Client(stream)).is_err() {
+                        break;
+                    }
                 }
+                // if the stream failed to be read from the socket
+                Err(e) => eprintln!("Couldn't read stream from socket: {:?}", e),
             }
-            // if the stream failed to be read from the socket
-            Err(e) => eprintln!("Couldn't read stream from socket: {:?}", e),
         }
-    }
-}
+    });
 
-fn handle_stream(i3: &mut I3Connection, stream: &mut UnixStream) -> Result<(), Error> {
-    // decode the stream's contents as UTF8 and save it into the string "message"
-    let mut message = String::new();
-    stream.read_to_string(&mut message)?;
-
-    println!("message: {:?}", &message); // DEBUG
-
-    // get Workspaces object from i3
-    let workspaces = i3.get_workspaces()?;
-
-    // establish the target workspace name (or title) for this action
-    let target = match message.as_str() {
-        // if the action is "toggle"
-        "toggle" => {
-            // determine the currently focused workspace and get the name of the output it's on
-            let active_output = match workspaces.workspaces.iter().find(|wp| wp.focused == true) {
-                Some(wp) => wp.output.clone(),
-                None => return Err(err_msg(format!("Couldn't find a focused workspace"))),
-            };
-
-            println!("active output: \"{}\"", active_output);
-
-            // determine the name of the second workspace on the active output
-            match workspaces.get_second_wp_with_output(&active_output) {
-                Some(wp) => wp.name.clone(),
-                None => {
-                    return Err(err_msg(format!(
-                        "Couldn't find a second workspace on the active output"
-                    )))
-                }
+    // reload the config file whenever the daemon receives SIGHUP
+    let signals = match Signals::new([signal_hook::SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => panic!("Couldn't register SIGHUP handler: {:?}", e),
+    };
+    let signal_tx = tx.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if signal_tx.send(DaemonEvent::Reload).is_err() {
+                break;
             }
         }
-
-        // if the action isn't "toggle", we have to ask the user to specify a target
-        _ => {
-            // spawn a dmenu process
-            let mut menu_proc = Command::new("dmenu")
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()?;
-
-            {
-                // get a ref to stdin of dmenu process
-                let stdin = match menu_proc.stdin.as_mut() {
-                    Some(s) => s,
-                    None => return Err(err_msg(format!("Couldn't get ref to stdin of dmenu"))),
-                };
-
-                // println!("workspace choices: {:?}", workspaces.choices_str());  // DEBUG
-
-                // write the list of workspaces to dmenu's stdin
-                stdin.write_all(workspaces.choices_str().as_bytes())?;
+    });
+
+    // subscribe to workspace events so that changes made outside fluidspaces (closing the last
+    // window on a workspace, keybindings, other tools) also trigger renumbering, and to window
+    // events so the saved session follows windows as they come and go
+    let events = match wm.subscribe() {
+        Ok(events) => events,
+        Err(e) => panic!("Couldn't subscribe to window manager events: {}", e),
+    };
+    thread::spawn(move || {
+        for event_res in events {
+            match event_res {
+                Ok(event) => {
+                    if tx.send(DaemonEvent::Wm(event)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
             }
+        }
+        // if the connection to the window manager is gone there is nothing left for the daemon
+        // to manage
+        process::exit(1);
+    });
+
+    // bring the numbering in line with fluidspaces' scheme before handling anything else
+    if let Err(e) = fixup(&mut *wm, &config) {
+        eprintln!("{}", e.cause());
+    }
 
-            // wait for dmenu to exit and collect all output on its stdout / stderr
-            let menu_output = menu_proc.wait_with_output()?;
-
-            // get the title chosen by the user from dmenu's stdout
-            let raw_title = String::from_utf8_lossy(&menu_output.stdout[..]);
-            // println!("choice (raw title) {:?}", raw_title);  // DEBUG
-            let title = raw_title.trim();
-            // println!("choice (title) {:?}", title);  // DEBUG
+    // start tracking the order in which workspaces are focused, beginning with the current one
+    let mut history = History::new();
+    if let Err(e) = update_history(&mut *wm, &mut history, &config, true) {
+        eprintln!("{}", e.cause());
+    }
 
-            // check to see if the user actually chose a target; if they didn't then we don't need
-            // to do anything else and can return early
-            if title == "" {
-                return Ok(());
-            }
+    // title the workspaces that only have a number; titles from before a restart look like the
+    // user's own and are kept
+    let mut auto_titled = AutoTitles::new();
+    if let Err(e) = auto_title(&mut *wm, &mut history, &config, &mut auto_titled) {
+        eprintln!("{}", e.cause());
+    }
 
-            // the target is either an existing workspace name (if a workspace
-            // with a matching title exists) or the combination of the next
-            // unused number and the chosen title itself (if a workspace with a
-            // matching title doesn't exist)
-            match workspaces.get_wp_with_title(title) {
-                Some(wp) => wp.name.clone(),
-                None => format!("{}:{}", workspaces.next_unused_number(), title),
-            }
+    // the session saved before the daemon started is the one `restore` brings back; it is read
+    // before anything is saved over it
+    let restorable = match session_path(&config).map(|path| Session::load(&path)) {
+        Some(Ok(session)) => session,
+        Some(Err(e)) => {
+            eprintln!("{}", e.cause());
+            None
         }
+        None => None,
     };
+    let mut saved = None;
+    save_session(&mut *wm, &config, &mut saved);
+
+    // start event loop - blocks until a client connects or the window manager reports an event
+    for event in rx {
+        match event {
+            DaemonEvent::Client(mut stream) => {
+                // process the stream; reloading is handled here because it replaces the config
+                // that every other action reads
+                let result = read_request(&mut stream).and_then(|request| match request.action {
+                    Action::Reload => reload(&mut *wm, &matches, &mut config)
+                        .map(|_| Response::Success { focused: None }),
+                    Action::Restore => restore(&mut *wm, &config, restorable.as_ref()),
+                    _ => handle_request(&mut *wm, &mut history, &config, &request),
+                });
+                let response = match result {
+                    Ok(response) => response,
+                    Err(e) => {
+                        eprintln!("{}", e.cause());
+                        Response::Error {
+                            message: e.cause().to_string(),
+                        }
+                    }
+                };
 
-    println!("target: {:?}", target); // DEBUG
-
-    // initialize empty vector of action commands
-    let mut action_cmds: Vec<String> = vec![];
-
-    // push command strings into the vector according to the requested action
-    match message.as_str() {
-        "go_to" | "toggle" => action_cmds.push(go_to(&target)),
-        "send_to" => action_cmds.push(send_to(&target)),
-        "bring_to" => {
-            action_cmds.push(send_to(&target));
-            action_cmds.push(go_to(&target));
-        }
-        message => {
-            return Err(err_msg(format!(
-                "Unexpected message received: {:?}",
-                message
-            )))
+                // report the outcome back to the client
+                if let Err(e) = send_response(&mut stream, &response) {
+                    eprintln!("Couldn't send reply to client: {}", e.cause());
+                }
+                save_session(&mut *wm, &config, &mut saved);
+            }
+            DaemonEvent::Wm(WmEvent::Workspace(change)) => {
+                if needs_fixup(change) {
+                    if let Err(e) = fixup(&mut *wm, &config) {
+                        eprintln!("{}", e.cause());
+                    }
+                }
+                let focus_changed = change == WorkspaceChange::Focus;
+                if let Err(e) = update_history(&mut *wm, &mut history, &config, focus_changed) {
+                    eprintln!("{}", e.cause());
+                }
+                if needs_fixup(change) {
+                    save_session(&mut *wm, &config, &mut saved);
+                }
+            }
+            DaemonEvent::Wm(WmEvent::Window(change, id)) => {
+                if change == WindowChange::New {
+                    if let Err(e) = route_window(&mut *wm, &config, id) {
+                        eprintln!("{}", e.cause());
+                    }
+                }
+                // focus and title changes don't move windows between workspaces, and titles
+                // change far too often to react to every time
+                if let WindowChange::New | WindowChange::Close | WindowChange::Move = change {
+                    if let Err(e) = auto_title(&mut *wm, &mut history, &config, &mut auto_titled) {
+                        eprintln!("{}", e.cause());
+                    }
+                    save_session(&mut *wm, &config, &mut saved);
+                }
+            }
+            DaemonEvent::Reload => {
+                if let Err(e) = reload(&mut *wm, &matches, &mut config) {
+                    eprintln!("{}", e.cause());
+                }
+            }
         }
     }
+}
 
-    // run action commands all at once
-    i3.run_commands(&action_cmds)?;
-
-    // initialize vector of promotion commands
-    let promote_cmds = match i3.get_workspaces()?.get_wp_with_focus() {
-        Some(wp) => vec![wp.promote()],
-        None => vec![],
+// read the config file named on the command line (or the default one), then apply the command
+// line options that override it
+fn load_config(matches: &ArgMatches) -> Result<Config, Error> {
+    let mut config = match matches.value_of("config") {
+        Some(path) => Config::load(&PathBuf::from(path))?,
+        None => match Config::default_path() {
+            Some(path) => Config::load(&path)?,
+            None => Config::default(),
+        },
     };
 
-    // run promotion commands all at once
-    i3.run_commands(&promote_cmds)?;
-
-    // initialize vector of fixup commands
-    let fixup_cmds = i3.get_workspaces()?.fixup_wps();
-
-    // run fixup commands all at once
-    i3.run_commands(&fixup_cmds)?;
+    if let Some(name) = matches.value_of("wm") {
+        config.wm = Some(name.parse()?);
+    }
+    if let Some(socket) = matches.value_of("socket") {
+        config.socket = Some(PathBuf::from(socket));
+    }
+    if let Some(order) = matches.value_of("order") {
+        config.order = order.parse()?;
+    }
 
-    Ok(())
+    Ok(config)
 }
 
-// testing stuff for later
+// replace the current config with a freshly loaded one; if the new config is invalid the old one
+// stays in effect
+fn reload(
+    wm: &mut dyn WindowManager,
+    matches: &ArgMatches,
+    config: &mut Config,
+) -> Result<(), Error> {
+    let new_config = load_config(matches)?;
+
+    // the socket is already bound and the window manager connected, so changes to either can
+    // only take effect after a restart
+    if new_config.socket != config.socket {
+        eprintln!("The new socket path will only be used after the daemon restarts");
+    }
+    if new_config.wm != config.wm {
+        eprintln!("The new window manager will only be used after the daemon restarts");
+    }
 
-// println!("{:?}", workspaces.choices_str());
+    *config = new_config;
 
-// println!("{:?}", workspaces.get_wp_with_title("rust"));
-// println!("{:?}", workspaces.get_wp_with_title("foo"));
+    // rename the workspaces in case the separator changed
+    fixup(wm, config)
+}
 
-// println!("{:?}", i3.promote_wp_title("rust"));
+// the session file from the config, or the default one
+fn session_path(config: &Config) -> Option<PathBuf> {
+    config.session.clone().or_else(Session::default_path)
+}
+
+// save the current session if it differs from the one saved last; failures are only reported,
+// since the daemon works fine without a session file
+fn save_session(wm: &mut dyn WindowManager, config: &Config, saved: &mut Option<Session>) {
+    let path = match session_path(config) {
+        Some(path) => path,
+        None => return,
+    };
+    let result = snapshot(wm, config).and_then(|session| {
+        if saved.as_ref() != Some(&session) {
+            session.save(&path)?;
+            *saved = Some(session);
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e.cause());
+    }
+}
 
-// println!("{:?}", i3.fixup_wps());
+// everything the daemon's event loop reacts to
+enum DaemonEvent {
+    // a client connected to the fluidspaces socket
+    Client(UnixStream),
+    // the window manager reported an event we subscribed to
+    Wm(WmEvent),
+    // the config file should be read again
+    Reload,
+}
 
-// println!("{:?}", i3.go_to("rust"));
-// println!("{:?}", i3.go_to("foo"));
+// whether a workspace event may have left a gap or a stale number in the workspace list; focus
+// and urgency changes don't affect numbering, and reacting to them would be wasted work
+fn needs_fixup(change: WorkspaceChange) -> bool {
+    match change {
+        WorkspaceChange::Init
+        | WorkspaceChange::Empty
+        | WorkspaceChange::Move
+        | WorkspaceChange::Rename
+        | WorkspaceChange::Reload
+        | WorkspaceChange::Restored => true,
+        WorkspaceChange::Focus | WorkspaceChange::Urgent | WorkspaceChange::Unknown => false,
+    }
+}
 
-// println!("{:?}", i3.send_to("rust"));
-// println!("{:?}", i3.send_to("foo"));
+fn send_response(stream: &mut UnixStream, response: &Response) -> Result<(), Error> {
+    stream.write_all(response.to_line()?.as_bytes())?;
+    Ok(())
+}
 
-// assert_eq!(parse_title_from_name("1:foo".as_bytes()).to_result().unwrap(), "foo");
-// assert_eq!(parse_title_from_name("1: foo".as_bytes()).to_result().unwrap(), "foo");
-// assert_eq!(parse_title_from_name("1 :foo".as_bytes()).to_result().unwrap(), "foo");
-// assert_eq!(parse_title_from_name("1 : foo".as_bytes()).to_result().unwrap(), "foo");
-// assert_eq!(parse_title_from_name("foo".as_bytes()).to_result().unwrap(), "foo");
-// assert_eq!(parse_title_from_name(":foo".as_bytes()).to_result().unwrap(), "foo");
-// // assert_eq!(parse_title_from_name("-1:foo".as_bytes()).to_result().unwrap(), "foo");
+fn read_request(stream: &mut UnixStream) -> Result<Request, Error> {
+    // read a single line (one request) from the stream and decode it as UTF8
+    let mut message = String::new();
+    BufReader::new(&*stream).read_line(&mut message)?;
 
-// println!("{:?}", parse_title_from_name("-1:foo".as_bytes()));
+    // parse the request sent by the client
+    Request::from_line(&message)
+}
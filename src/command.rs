@@ -0,0 +1,190 @@
+use std::fmt;
+
+// a command for the window manager; rendering it with `to_string` quotes every name, so titles
+// can't break out of the command they're used in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    // focus the workspace with this name, creating it if it doesn't exist
+    Workspace(String),
+    // move the focused container to the workspace with this name, creating it if needed
+    MoveToWorkspace(String),
+    RenameWorkspace { from: String, to: String },
+    // move every window on the workspace named `from` to the one named `to`
+    MoveWorkspaceContents { from: String, to: String },
+    // focus the workspace that is visible on this output
+    FocusOutput(String),
+    // move the workspace with this name, which must have windows on it, to another output
+    MoveWorkspaceToOutput { workspace: String, output: String },
+    // move the focused workspace to another output, even if it's empty
+    MoveFocusedWorkspaceToOutput(String),
+    // load a layout file into the focused workspace (i3 only)
+    AppendLayout(String),
+    // run a shell command; its windows open on whichever workspace has focus when they appear
+    Exec(String),
+    // move the window with this container id to the workspace with this name, creating it if
+    // needed
+    MoveWindowToWorkspace { id: i64, workspace: String },
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Workspace(ref name) => write!(f, "workspace {}", quote(name)),
+            Command::MoveToWorkspace(ref name) => {
+                write!(f, "move container to workspace {}", quote(name))
+            }
+            Command::RenameWorkspace { ref from, ref to } => {
+                write!(f, "rename workspace {} to {}", quote(from), quote(to))
+            }
+            Command::MoveWorkspaceContents { ref from, ref to } => write!(
+                f,
+                "[workspace={}] move container to workspace {}",
+                quote(&format!("^{}$", regex_escape(from))),
+                quote(to)
+            ),
+            Command::FocusOutput(ref output) => write!(f, "focus output {}", quote(output)),
+            Command::MoveWorkspaceToOutput {
+                ref workspace,
+                ref output,
+            } => write!(
+                f,
+                "[workspace={}] move workspace to output {}",
+                quote(&format!("^{}$", regex_escape(workspace))),
+                quote(output)
+            ),
+            Command::MoveFocusedWorkspaceToOutput(ref output) => {
+                write!(f, "move workspace to output {}", quote(output))
+            }
+            Command::AppendLayout(ref path) => write!(f, "append_layout {}", quote(path)),
+            // without --no-startup-id, i3 shows a busy cursor until a window appears
+            Command::Exec(ref cmd) => write!(f, "exec --no-startup-id {}", quote(cmd)),
+            Command::MoveWindowToWorkspace { id, ref workspace } => write!(
+                f,
+                "[con_id={}] move container to workspace {}",
+                id,
+                quote(workspace)
+            ),
+        }
+    }
+}
+
+// wrap a string in double quotes; i3 and sway unescape \" and \\ inside quoted strings, and
+// semicolons and commas only separate commands outside of them
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// criteria are regular expressions, so names have to be escaped to be matched literally
+fn regex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+
+    #[test]
+    fn names_are_quoted() {
+        assert_eq!(
+            Command::Workspace(String::from("1:mail")).to_string(),
+            "workspace \"1:mail\""
+        );
+        assert_eq!(
+            Command::MoveToWorkspace(String::from("2:web")).to_string(),
+            "move container to workspace \"2:web\""
+        );
+        assert_eq!(
+            Command::RenameWorkspace {
+                from: String::from("3:rust"),
+                to: String::from("1:rust"),
+            }
+            .to_string(),
+            "rename workspace \"3:rust\" to \"1:rust\""
+        );
+        assert_eq!(
+            Command::MoveWorkspaceContents {
+                from: String::from("2:web"),
+                to: String::from("1:mail"),
+            }
+            .to_string(),
+            "[workspace=\"^2:web$\"] move container to workspace \"1:mail\""
+        );
+        assert_eq!(
+            Command::FocusOutput(String::from("HDMI-1")).to_string(),
+            "focus output \"HDMI-1\""
+        );
+        assert_eq!(
+            Command::MoveWorkspaceToOutput {
+                workspace: String::from("4:web"),
+                output: String::from("HDMI-1"),
+            }
+            .to_string(),
+            "[workspace=\"^4:web$\"] move workspace to output \"HDMI-1\""
+        );
+        assert_eq!(
+            Command::MoveFocusedWorkspaceToOutput(String::from("DP-2")).to_string(),
+            "move workspace to output \"DP-2\""
+        );
+        assert_eq!(
+            Command::AppendLayout(String::from("~/.i3/mail.json")).to_string(),
+            "append_layout \"~/.i3/mail.json\""
+        );
+        assert_eq!(
+            Command::Exec(String::from("firefox \"https://example.com\"")).to_string(),
+            "exec --no-startup-id \"firefox \\\"https://example.com\\\"\""
+        );
+        assert_eq!(
+            Command::MoveWindowToWorkspace {
+                id: 94321,
+                workspace: String::from("2:web"),
+            }
+            .to_string(),
+            "[con_id=94321] move container to workspace \"2:web\""
+        );
+    }
+
+    #[test]
+    fn criteria_are_regex_escaped() {
+        // the regex escapes are backslashes themselves, so they get escaped again by the quoting
+        assert_eq!(
+            Command::MoveWorkspaceContents {
+                from: String::from("1:c++ (\"old\")"),
+                to: String::from("2:x"),
+            }
+            .to_string(),
+            r#"[workspace="^1:c\\+\\+ \\(\"old\"\\)$"] move container to workspace "2:x""#
+        );
+    }
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!(
+            Command::Workspace(String::from("1:foo\"; exec rm -rf ~")).to_string(),
+            "workspace \"1:foo\\\"; exec rm -rf ~\""
+        );
+        assert_eq!(
+            Command::Workspace(String::from("1:a\\\"b")).to_string(),
+            "workspace \"1:a\\\\\\\"b\""
+        );
+        assert_eq!(
+            Command::Workspace(String::from("1:trailing\\")).to_string(),
+            "workspace \"1:trailing\\\\\""
+        );
+    }
+}
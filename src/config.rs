@@ -0,0 +1,486 @@
+use failure::err_msg;
+use failure::Error;
+
+use toml;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use history::Order;
+use menu::MENU_NAMES;
+use wm::Window;
+use wm::WmKind;
+use Numbering;
+use Pins;
+use DEFAULT_SEPARATOR;
+
+// settings for the daemon, read from config.toml; every field is optional in the file
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // window manager to connect to; detected from the environment when not set. only read at
+    // startup
+    pub wm: Option<WmKind>,
+    // path of the socket to listen on; only read at startup
+    pub socket: Option<PathBuf>,
+    // menu program used for requests that don't name one
+    pub menu: String,
+    // extra arguments for each menu program, keyed by menu name
+    pub menu_args: HashMap<String, Vec<String>>,
+    // menu order used for requests that don't specify one
+    pub order: Order,
+    // text between the number and the title in workspace names
+    pub separator: String,
+    // titles that always get the same workspace number
+    pub pins: Pins,
+    // whether workspaces are numbered across all outputs or on each output separately
+    pub numbering: NumberingMode,
+    // with per-output numbering, how far apart the first numbers of neighbouring outputs are
+    pub output_step: usize,
+    // what to put on workspaces when fluidspaces creates them; the first one that matches is used
+    pub templates: Vec<Template>,
+    // where the daemon keeps the snapshot of the workspaces that `restore` brings back
+    pub session: Option<PathBuf>,
+    // where to move new windows; the first one that matches is used
+    pub rules: Vec<Rule>,
+    // whether workspaces that only have a number are titled after the classes of their windows
+    pub auto_titles: bool,
+}
+
+// a layout and programs for new workspaces whose title is `title` or matches `pattern`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    pub title: Option<String>,
+    // a glob pattern, where * matches any text and ? any single character
+    pub pattern: Option<String>,
+    // layout file saved with i3-save-tree, loaded with append_layout
+    pub layout: Option<String>,
+    // commands to run with exec once the workspace is focused
+    #[serde(default)]
+    pub exec: Vec<String>,
+}
+
+impl Template {
+    pub fn matches(&self, title: &str) -> bool {
+        match (self.title.as_ref(), self.pattern.as_ref()) {
+            (Some(t), _) => t == title,
+            (None, Some(pattern)) => glob_match(pattern, title),
+            (None, None) => false,
+        }
+    }
+}
+
+// move new windows whose class, instance and title match the given glob patterns to the workspace
+// titled `workspace`; patterns that aren't given match anything
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    // the window's title, not the workspace's
+    pub title: Option<String>,
+    pub workspace: String,
+}
+
+impl Rule {
+    pub fn matches(&self, window: &Window) -> bool {
+        let field_matches = |pattern: &Option<String>, value: &Option<String>| match *pattern {
+            Some(ref pattern) => value.as_ref().is_some_and(|v| glob_match(pattern, v)),
+            None => true,
+        };
+        field_matches(&self.class, &window.class)
+            && field_matches(&self.instance, &window.instance)
+            && field_matches(&self.title, &window.title)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberingMode {
+    Global,
+    PerOutput,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            wm: None,
+            socket: None,
+            menu: String::from("dmenu"),
+            menu_args: HashMap::new(),
+            order: Order::Default,
+            separator: String::from(DEFAULT_SEPARATOR),
+            pins: Pins::new(),
+            numbering: NumberingMode::Global,
+            output_step: 0,
+            templates: vec![],
+            session: None,
+            rules: vec![],
+            auto_titles: false,
+        }
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/fluidspaces/config.toml, falling back to ~/.config when XDG_CONFIG_HOME
+    // isn't set
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+            Some(ref dir) if dir.is_absolute() => dir.clone(),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("fluidspaces").join("config.toml"))
+    }
+
+    // read and validate the config file at the given path; a missing file just means every
+    // setting keeps its default
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(err_msg(format!("Couldn't read config {:?}: {}", path, e))),
+        };
+
+        match Config::parse(&contents) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(err_msg(format!("Invalid config {:?}: {}", path, e))),
+        }
+    }
+
+    // parse and validate the contents of a config file
+    pub fn parse(contents: &str) -> Result<Config, Error> {
+        let config: Config = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn numbering(&self) -> Numbering {
+        match self.numbering {
+            NumberingMode::Global => Numbering::Global,
+            NumberingMode::PerOutput => Numbering::PerOutput {
+                step: self.output_step,
+            },
+        }
+    }
+
+    // the template for new workspaces with this title, if any
+    pub fn template_for(&self, title: &str) -> Option<&Template> {
+        self.templates.iter().find(|t| t.matches(title))
+    }
+
+    // the rule for a newly opened window, if any
+    pub fn rule_for(&self, window: &Window) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.matches(window))
+    }
+
+    // extra arguments configured for the named menu program
+    pub fn menu_args(&self, menu: &str) -> &[String] {
+        match self.menu_args.get(menu) {
+            Some(args) => args,
+            None => &[],
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if !MENU_NAMES.contains(&self.menu.as_str()) {
+            return Err(err_msg(format!(
+                "menu must be one of {}, not {:?}",
+                MENU_NAMES.join(", "),
+                self.menu
+            )));
+        }
+
+        if let Some(menu) = self
+            .menu_args
+            .keys()
+            .find(|m| !MENU_NAMES.contains(&m.as_str()))
+        {
+            return Err(err_msg(format!(
+                "menu_args has arguments for unknown menu {:?}",
+                menu
+            )));
+        }
+
+        // i3 takes every leading digit as part of the workspace number, and quotes would break
+        // the commands the names are used in
+        match self.separator.chars().next() {
+            None => return Err(err_msg("separator can't be empty")),
+            Some(c) if c.is_ascii_digit() => {
+                return Err(err_msg("separator can't start with a digit"))
+            }
+            _ => (),
+        }
+        if self.separator.contains('"') {
+            return Err(err_msg("separator can't contain double quotes"));
+        }
+
+        // i3 treats number 0 (which fluidspaces uses for promotion) and below as no number at all
+        if let Some((title, _)) = self.pins.iter().find(|&(_, &n)| n == 0) {
+            return Err(err_msg(format!(
+                "pins can't use number 0 (pinned to {:?})",
+                title
+            )));
+        }
+        for (title, number) in &self.pins {
+            if let Some((other, _)) = self.pins.iter().find(|&(t, n)| n == number && t < title) {
+                return Err(err_msg(format!(
+                    "pins give number {} to both {:?} and {:?}",
+                    number, other, title
+                )));
+            }
+        }
+
+        if self.output_step != 0 && self.numbering != NumberingMode::PerOutput {
+            return Err(err_msg(
+                "output_step only applies to numbering = \"per-output\"",
+            ));
+        }
+
+        for template in &self.templates {
+            if template.title.is_some() == template.pattern.is_some() {
+                return Err(err_msg(
+                    "every template needs either a title or a pattern, but not both",
+                ));
+            }
+            if template.layout.is_none() && template.exec.is_empty() {
+                return Err(err_msg(format!(
+                    "the template for {:?} has neither a layout nor commands to exec",
+                    template
+                        .title
+                        .as_ref()
+                        .or(template.pattern.as_ref())
+                        .unwrap()
+                )));
+            }
+        }
+
+        for rule in &self.rules {
+            if rule.class.is_none() && rule.instance.is_none() && rule.title.is_none() {
+                return Err(err_msg(format!(
+                    "the rule for {:?} needs a class, instance or title",
+                    rule.workspace
+                )));
+            }
+            if rule.workspace.trim().is_empty() {
+                return Err(err_msg("rules can't move windows to an empty title"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// whether the whole of `text` matches the glob `pattern`
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // where to resume after the last *, if the text after it stops matching
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // let the * swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, Config};
+    use history::Order;
+    use std::path::{Path, PathBuf};
+    use wm::Window;
+    use wm::WmKind;
+    use Numbering;
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        assert_eq!(
+            Config::load(Path::new("/nonexistent/fluidspaces/config.toml")).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn every_setting_is_read() {
+        let config = Config::parse(
+            r#"
+            wm = "sway"
+            socket = "/tmp/fs.sock"
+            menu = "rofi"
+            order = "last-used"
+            separator = " | "
+            numbering = "per-output"
+            output_step = 10
+
+            [menu_args]
+            rofi = ["-i", "-p", "workspace"]
+
+            [pins]
+            mail = 1
+            chat = 9
+
+            [[templates]]
+            title = "mail"
+            exec = ["thunderbird"]
+
+            [[templates]]
+            pattern = "rust-*"
+            layout = "~/.config/i3/rust.json"
+            exec = ["alacritty", "emacs"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.wm, Some(WmKind::Sway));
+        assert_eq!(config.socket, Some(PathBuf::from("/tmp/fs.sock")));
+        assert_eq!(config.menu, "rofi");
+        assert_eq!(config.order, Order::LastUsed);
+        assert_eq!(config.separator, " | ");
+        assert_eq!(config.menu_args("rofi"), &["-i", "-p", "workspace"]);
+        assert!(config.menu_args("dmenu").is_empty());
+        assert_eq!(config.pins.get("mail"), Some(&1));
+        assert_eq!(config.pins.get("chat"), Some(&9));
+        assert_eq!(config.numbering(), Numbering::PerOutput { step: 10 });
+        assert_eq!(
+            config.template_for("mail").unwrap().exec,
+            vec!["thunderbird"]
+        );
+        assert_eq!(
+            config.template_for("rust-fluidspaces").unwrap().layout,
+            Some(String::from("~/.config/i3/rust.json"))
+        );
+        assert!(config.template_for("rust").is_none());
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        assert!(Config::parse("[[templates]]\nexec = [\"xterm\"]").is_err());
+        assert!(Config::parse(
+            "[[templates]]\ntitle = \"a\"\npattern = \"a*\"\nexec = [\"xterm\"]"
+        )
+        .is_err());
+        assert!(Config::parse("[[templates]]\ntitle = \"a\"").is_err());
+    }
+
+    #[test]
+    fn rules_match_windows() {
+        let config = Config::parse(
+            r#"
+            [[rules]]
+            class = "Thunderbird"
+            workspace = "mail"
+
+            [[rules]]
+            class = "Alacritty"
+            title = "*vim*"
+            workspace = "code"
+            "#,
+        )
+        .unwrap();
+        let window = |class: &str, title: Option<&str>| Window {
+            id: 1,
+            workspace: String::from("1"),
+            class: Some(String::from(class)),
+            instance: None,
+            title: title.map(String::from),
+        };
+        assert_eq!(
+            config
+                .rule_for(&window("Thunderbird", None))
+                .unwrap()
+                .workspace,
+            "mail"
+        );
+        assert_eq!(
+            config
+                .rule_for(&window("Alacritty", Some("nvim main.rs")))
+                .unwrap()
+                .workspace,
+            "code"
+        );
+        assert!(config.rule_for(&window("Alacritty", Some("zsh"))).is_none());
+        assert!(config.rule_for(&window("Alacritty", None)).is_none());
+
+        assert!(Config::parse("[[rules]]\nworkspace = \"mail\"").is_err());
+        assert!(Config::parse("[[rules]]\nclass = \"a\"\nworkspace = \" \"").is_err());
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("rust-*", "rust-"));
+        assert!(glob_match("rust-*", "rust-fluidspaces"));
+        assert!(glob_match("*mail*", "gmail-work"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*a*b", "xaxab"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("rust-*", "rust"));
+        assert!(!glob_match("*.rs", "main.rsx"));
+    }
+
+    #[test]
+    fn unknown_setting_is_rejected() {
+        let e = Config::parse("colour = \"red\"").unwrap_err();
+        assert!(e.to_string().contains("colour"));
+    }
+
+    #[test]
+    fn wrong_type_is_rejected() {
+        assert!(Config::parse("menu = 3").is_err());
+        assert!(Config::parse("order = \"sideways\"").is_err());
+        assert!(Config::parse("wm = \"dwm\"").is_err());
+    }
+
+    #[test]
+    fn unknown_menu_is_rejected() {
+        assert!(Config::parse("menu = \"zenity\"").is_err());
+        assert!(Config::parse("[menu_args]\nzenity = []").is_err());
+    }
+
+    #[test]
+    fn bad_pins_are_rejected() {
+        assert!(Config::parse("[pins]\nmail = 0").is_err());
+        assert!(Config::parse("[pins]\nmail = -1").is_err());
+        assert!(Config::parse("[pins]\nmail = 1\nchat = 1").is_err());
+    }
+
+    #[test]
+    fn output_step_needs_per_output_numbering() {
+        assert!(Config::parse("output_step = 10").is_err());
+        assert!(Config::parse("numbering = \"per-output\"\noutput_step = 10").is_ok());
+        assert_eq!(
+            Config::parse("numbering = \"per-output\"")
+                .unwrap()
+                .numbering(),
+            Numbering::PerOutput { step: 0 }
+        );
+    }
+
+    #[test]
+    fn bad_separator_is_rejected() {
+        assert!(Config::parse("separator = \"\"").is_err());
+        assert!(Config::parse("separator = \"1\"").is_err());
+        assert!(Config::parse("separator = '\"'").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use failure::err_msg;
+use failure::Error;
+
+use i3ipc::reply::Workspaces;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use WorkspaceExt;
+use WorkspacesExt;
+
+// names accepted by `Order::from_str`, in the order they should be offered to users
+pub const ORDER_NAMES: &[&str] = &["default", "last-used", "alphabetical", "frequency"];
+
+// how the workspaces are arranged in the menu
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Order {
+    // by workspace number
+    Default,
+    // most recently focused first
+    LastUsed,
+    // by title, ignoring case
+    Alphabetical,
+    // most often chosen first
+    Frequency,
+}
+
+impl FromStr for Order {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Order, Error> {
+        match s {
+            "default" => Ok(Order::Default),
+            "last-used" => Ok(Order::LastUsed),
+            "alphabetical" => Ok(Order::Alphabetical),
+            "frequency" => Ok(Order::Frequency),
+            s => Err(err_msg(format!("Unknown order: {:?}", s))),
+        }
+    }
+}
+
+// the order in which workspaces were focused and how often each was chosen, tracked by title
+// because the numbers in workspace names change every time fluidspaces renumbers them
+#[derive(Debug, Default)]
+pub struct History {
+    // titles of every known workspace, most recently focused first
+    global: Vec<String>,
+    // the same, separately for each output
+    outputs: HashMap<String, Vec<String>>,
+    // how many times each title was chosen from the menu or given as a target; kept even after
+    // the workspace disappears so that recreating it later picks up where it left off
+    chosen: HashMap<String, usize>,
+    // the pass through the workspaces that next/prev are in the middle of
+    cycle: Option<Cycle>,
+}
+
+// the titles in the order a pass with next/prev started with, and the title it went to last
+#[derive(Debug)]
+struct Cycle {
+    titles: Vec<String>,
+    current: String,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    // record that the workspace with this title, on this output, just gained focus
+    pub fn focus(&mut self, title: &str, output: &str) {
+        // focusing anything but the workspace next/prev went to ends their pass
+        if self.cycle.as_ref().is_some_and(|c| c.current != title) {
+            self.cycle = None;
+        }
+
+        // the workspace may have been moved here from another output
+        for titles in self.outputs.values_mut() {
+            titles.retain(|t| t != title);
+        }
+        self.global.retain(|t| t != title);
+
+        self.global.insert(0, title.to_owned());
+        self.outputs
+            .entry(output.to_owned())
+            .or_default()
+            .insert(0, title.to_owned());
+    }
+
+    // record that the user picked this title as the target of an action
+    pub fn choose(&mut self, title: &str) {
+        *self.chosen.entry(title.to_owned()).or_insert(0) += 1;
+    }
+
+    // how many times this title was picked as the target of an action
+    pub fn times_chosen(&self, title: &str) -> usize {
+        self.chosen.get(title).cloned().unwrap_or(0)
+    }
+
+    // the title of a workspace changed; it keeps its place in the history
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(count) = self.chosen.remove(old) {
+            *self.chosen.entry(new.to_owned()).or_insert(0) += count;
+        }
+        for title in self.global.iter_mut().chain(
+            self.outputs
+                .values_mut()
+                .flat_map(|titles| titles.iter_mut()),
+        ) {
+            if title == old {
+                *title = new.to_owned();
+            }
+        }
+    }
+
+    // drop workspaces that no longer exist, and make sure every existing workspace is listed
+    // under the output it is on; workspaces we haven't seen focused yet go at the end
+    pub fn sync(&mut self, workspaces: &Workspaces, separator: &str) {
+        let exists = |title: &str| workspaces.get_wp_with_title(title, separator).is_some();
+        self.global.retain(|t| exists(t));
+        for (output, titles) in &mut self.outputs {
+            titles.retain(|t| match workspaces.get_wp_with_title(t, separator) {
+                Some(wp) => wp.output == *output,
+                None => false,
+            });
+        }
+
+        let mut wps: Vec<_> = workspaces.workspaces.iter().collect();
+        wps.sort_by_key(|wp| wp.num);
+        for wp in wps {
+            let title = wp.title(separator);
+            if !self.global.iter().any(|t| t == title) {
+                self.global.push(title.to_owned());
+            }
+            let titles = self.outputs.entry(wp.output.clone()).or_default();
+            if !titles.iter().any(|t| t == title) {
+                titles.push(title.to_owned());
+            }
+        }
+    }
+
+    // titles of all workspaces, most recently focused first
+    pub fn last_used(&self) -> &[String] {
+        &self.global
+    }
+
+    // titles of the workspaces on one output, most recently focused first
+    pub fn last_used_on(&self, output: &str) -> &[String] {
+        match self.outputs.get(output) {
+            Some(titles) => titles,
+            None => &[],
+        }
+    }
+
+    // the title after (or before) `current` in `titles`, wrapping around at the ends; consecutive
+    // steps keep using the order the first one saw, so that stepping through the most recently
+    // used workspaces doesn't reshuffle them on every step
+    pub fn step(&mut self, titles: &[&str], current: &str, forward: bool) -> Option<String> {
+        let order: Vec<String> = match self.cycle {
+            // workspaces that have disappeared since are skipped
+            Some(ref cycle) if cycle.current == current => cycle
+                .titles
+                .iter()
+                .filter(|t| titles.contains(&t.as_str()))
+                .cloned()
+                .collect(),
+            _ => titles.iter().map(|t| (*t).to_owned()).collect(),
+        };
+        let len = order.len();
+        let i = order.iter().position(|t| t == current)?;
+        let next = if forward {
+            order[(i + 1) % len].clone()
+        } else {
+            order[(i + len - 1) % len].clone()
+        };
+
+        self.cycle = Some(Cycle {
+            titles: order,
+            current: next.clone(),
+        });
+        Some(next)
+    }
+
+    // the title of the workspace that was focused before the one with the given title, looking only
+    // at the workspaces on `output` if one is given
+    pub fn previous(&self, current: &str, output: Option<&str>) -> Option<&str> {
+        let titles = match output {
+            Some(output) => self.last_used_on(output),
+            None => self.last_used(),
+        };
+        titles.iter().find(|t| *t != current).map(|t| t.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use i3ipc::reply::{Workspace, Workspaces};
+
+    fn wp(name: &str, output: &str) -> Workspace {
+        Workspace {
+            num: name.split(':').next().unwrap().parse().unwrap_or(-1),
+            name: name.to_owned(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: output.to_owned(),
+        }
+    }
+
+    fn titles(titles: &[String]) -> Vec<&str> {
+        titles.iter().map(|t| t.as_str()).collect()
+    }
+
+    #[test]
+    fn focus_moves_title_to_front() {
+        let mut history = History::new();
+        history.focus("a", "left");
+        history.focus("b", "left");
+        history.focus("c", "right");
+        history.focus("a", "left");
+        assert_eq!(titles(history.last_used()), vec!["a", "c", "b"]);
+        assert_eq!(titles(history.last_used_on("left")), vec!["a", "b"]);
+        assert_eq!(titles(history.last_used_on("right")), vec!["c"]);
+        assert!(history.last_used_on("nowhere").is_empty());
+    }
+
+    #[test]
+    fn focus_on_another_output_moves_workspace() {
+        let mut history = History::new();
+        history.focus("a", "left");
+        history.focus("a", "right");
+        assert!(history.last_used_on("left").is_empty());
+        assert_eq!(titles(history.last_used_on("right")), vec!["a"]);
+    }
+
+    #[test]
+    fn previous_skips_current() {
+        let mut history = History::new();
+        assert_eq!(history.previous("a", None), None);
+        history.focus("a", "left");
+        assert_eq!(history.previous("a", None), None);
+        history.focus("b", "right");
+        assert_eq!(history.previous("b", None), Some("a"));
+        assert_eq!(history.previous("a", None), Some("b"));
+    }
+
+    #[test]
+    fn previous_can_stay_on_one_output() {
+        let mut history = History::new();
+        history.focus("a", "left");
+        history.focus("b", "right");
+        history.focus("c", "left");
+        assert_eq!(history.previous("c", None), Some("b"));
+        assert_eq!(history.previous("c", Some("left")), Some("a"));
+        assert_eq!(history.previous("b", Some("right")), None);
+    }
+
+    #[test]
+    fn rename_keeps_position() {
+        let mut history = History::new();
+        history.focus("a", "left");
+        history.focus("b", "left");
+        history.choose("a");
+        history.rename("a", "z");
+        assert_eq!(titles(history.last_used()), vec!["b", "z"]);
+        assert_eq!(history.times_chosen("a"), 0);
+        assert_eq!(history.times_chosen("z"), 1);
+        assert_eq!(titles(history.last_used_on("left")), vec!["b", "z"]);
+    }
+
+    #[test]
+    fn steps_keep_their_order_until_focus_moves_elsewhere() {
+        let mut history = History::new();
+        assert_eq!(
+            history.step(&["a", "b", "c"], "a", true),
+            Some(String::from("b"))
+        );
+        history.focus("b", "left");
+        // the order changed because b was focused, but the pass goes on from where it was
+        assert_eq!(
+            history.step(&["b", "a", "c"], "b", true),
+            Some(String::from("c"))
+        );
+        history.focus("c", "left");
+        assert_eq!(
+            history.step(&["c", "b", "a"], "c", true),
+            Some(String::from("a"))
+        );
+        assert_eq!(
+            history.step(&["a", "c", "b"], "a", false),
+            Some(String::from("c"))
+        );
+
+        // focusing something else starts a new pass
+        history.focus("b", "left");
+        assert_eq!(
+            history.step(&["b", "c", "a"], "b", false),
+            Some(String::from("a"))
+        );
+        assert_eq!(history.step(&["b"], "x", true), None);
+    }
+
+    #[test]
+    fn choices_are_counted() {
+        let mut history = History::new();
+        assert_eq!(history.times_chosen("a"), 0);
+        history.choose("a");
+        history.choose("a");
+        history.choose("b");
+        assert_eq!(history.times_chosen("a"), 2);
+        assert_eq!(history.times_chosen("b"), 1);
+    }
+
+    #[test]
+    fn sync_drops_missing_and_appends_unseen() {
+        let mut history = History::new();
+        history.focus("gone", "left");
+        history.choose("gone");
+        history.focus("b", "left");
+        let workspaces = Workspaces {
+            workspaces: vec![wp("1:b", "left"), wp("2:c", "right"), wp("3:a", "left")],
+        };
+        history.sync(&workspaces, ":");
+        assert_eq!(titles(history.last_used()), vec!["b", "c", "a"]);
+        assert_eq!(titles(history.last_used_on("left")), vec!["b", "a"]);
+        assert_eq!(titles(history.last_used_on("right")), vec!["c"]);
+        assert_eq!(history.times_chosen("gone"), 1);
+    }
+}
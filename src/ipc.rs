@@ -0,0 +1,128 @@
+use failure::err_msg;
+use failure::Error;
+
+use serde_json;
+use serde_json::Value;
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+// message types of the i3 IPC protocol; sway speaks the same protocol on its own socket
+pub const RUN_COMMAND: u32 = 0;
+pub const GET_WORKSPACES: u32 = 1;
+pub const SUBSCRIBE: u32 = 2;
+pub const GET_OUTPUTS: u32 = 3;
+pub const GET_TREE: u32 = 4;
+
+// events are told apart from replies by the highest bit of the message type; the rest of the
+// bits say what kind of event it is
+pub const EVENT: u32 = 1 << 31;
+pub const WORKSPACE_EVENT: u32 = EVENT;
+pub const WINDOW_EVENT: u32 = EVENT | 3;
+
+const MAGIC: &[u8] = b"i3-ipc";
+
+// a connection to the IPC socket of i3 or sway; every message is the magic string, the payload
+// length and the message type (both 32 bit, native byte order) followed by the payload
+pub struct IpcSocket {
+    stream: UnixStream,
+}
+
+impl IpcSocket {
+    pub fn connect(path: &Path) -> Result<IpcSocket, Error> {
+        match UnixStream::connect(path) {
+            Ok(stream) => Ok(IpcSocket { stream }),
+            Err(e) => Err(err_msg(format!("Couldn't connect to {:?}: {}", path, e))),
+        }
+    }
+
+    pub fn send(&mut self, msg_type: u32, payload: &str) -> io::Result<()> {
+        let mut message = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+        message.extend_from_slice(MAGIC);
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&msg_type.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        self.stream.write_all(&message)
+    }
+
+    // read the next message, returning its type and payload; an error here means the connection
+    // can't be used any more
+    pub fn receive(&mut self) -> io::Result<(u32, Vec<u8>)> {
+        let mut header = [0u8; 14];
+        self.stream.read_exact(&mut header)?;
+        if &header[..6] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message doesn't start with the i3-ipc magic string",
+            ));
+        }
+
+        let mut len = [0u8; 4];
+        let mut msg_type = [0u8; 4];
+        len.copy_from_slice(&header[6..10]);
+        msg_type.copy_from_slice(&header[10..14]);
+
+        let mut payload = vec![0u8; u32::from_ne_bytes(len) as usize];
+        self.stream.read_exact(&mut payload)?;
+        Ok((u32::from_ne_bytes(msg_type), payload))
+    }
+
+    // send a message and wait for the reply to it
+    pub fn request(&mut self, msg_type: u32, payload: &str) -> Result<Value, Error> {
+        self.send(msg_type, payload)?;
+        loop {
+            let (reply_type, reply) = self.receive()?;
+            // events may arrive on a subscribed connection before the reply
+            if reply_type & EVENT != 0 {
+                continue;
+            }
+            if reply_type != msg_type {
+                return Err(err_msg(format!(
+                    "Expected a reply of type {}, got {}",
+                    msg_type, reply_type
+                )));
+            }
+            return Ok(serde_json::from_slice(&reply)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IpcSocket, GET_WORKSPACES, WORKSPACE_EVENT};
+    use serde_json::Value;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn request_skips_events_and_decodes_reply() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let mut client = IpcSocket { stream: client };
+        let mut server = IpcSocket { stream: server };
+
+        server
+            .send(WORKSPACE_EVENT, "{\"change\":\"focus\"}")
+            .unwrap();
+        server.send(GET_WORKSPACES, "[]").unwrap();
+        assert_eq!(
+            client.request(GET_WORKSPACES, "").unwrap(),
+            Value::Array(vec![])
+        );
+
+        let (msg_type, payload) = server.receive().unwrap();
+        assert_eq!(msg_type, GET_WORKSPACES);
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        use std::io::Write;
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let mut client = IpcSocket { stream: client };
+        server.write_all(b"x3-ipc\0\0\0\0\0\0\0\0").unwrap();
+        assert!(client.receive().is_err());
+    }
+}
@@ -1,78 +1,196 @@
 extern crate failure;
 extern crate i3ipc;
+extern crate libc;
 #[macro_use]
 extern crate nom;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 extern crate unicode_segmentation;
+pub mod actions;
+pub mod command;
+pub mod config;
+pub mod history;
+mod ipc;
+pub mod menu;
+#[cfg(test)]
+mod mock;
 mod parser;
+pub mod protocol;
+pub mod session;
+pub mod socket;
+pub mod wm;
 
-use failure::Error;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 use i3ipc::reply::Workspace;
 use i3ipc::reply::Workspaces;
-use i3ipc::I3Connection;
 
+use command::Command;
+use history::History;
+use history::Order;
 use parser::title_from_name;
 
-pub trait I3ConnectionExt {
-    fn run_commands(&mut self, cmds: &[String]) -> Result<(), Error>;
-}
+// the separator between workspace number and title when none is configured
+pub const DEFAULT_SEPARATOR: &str = ":";
 
-impl I3ConnectionExt for I3Connection {
-    fn run_commands(&mut self, cmds: &[String]) -> Result<(), Error> {
-        cmds.iter().for_each(|c| println!("`{}`", c));
-        self.run_command(&cmds.join("; "))?;
-        Ok(())
-    }
+// workspace titles that always get the same number, keyed by title; these numbers are never given
+// to other workspaces, even while the pinned workspace doesn't exist
+pub type Pins = HashMap<String, usize>;
+
+// titles that fluidspaces made up from the windows on a workspace rather than the user choosing
+// them, so they may be changed again as windows come and go
+pub type AutoTitles = HashSet<String>;
+
+// how numbers are handed out to the workspaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    // one sequence across all outputs, in the order the window manager lists the workspaces
+    Global,
+    // a sequence for each output, with the outputs taken from left to right; the numbers on the
+    // nth output (counting from 0) start at n * step + 1, or right after the numbers of the output
+    // to its left if it has more workspaces than that leaves room for. with a step of 0 every
+    // output starts at 1
+    PerOutput { step: usize },
 }
 
 pub trait WorkspacesExt {
-    fn fixup_wps(&self) -> Vec<String>;
-    fn choices_str(&self) -> String;
+    fn fixup_wps(&self, separator: &str, pins: &Pins, numbering: Numbering) -> Vec<Command>;
+    fn move_wp(
+        &self,
+        title: &str,
+        index: usize,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+    ) -> Vec<Command>;
+    fn sorted_wps(
+        &self,
+        order: Order,
+        history: &History,
+        separator: &str,
+        output: Option<&str>,
+    ) -> Vec<&Workspace>;
 
     fn get_wp_with_focus(&self) -> Option<&Workspace>;
     fn get_wp_with_name(&self, name: &str) -> Option<&Workspace>;
     fn get_wp_with_number(&self, number: usize) -> Option<&Workspace>;
-    fn get_wp_with_title(&self, title: &str) -> Option<&Workspace>;
+    fn get_wp_with_title(&self, title: &str, separator: &str) -> Option<&Workspace>;
+    fn get_wp_at_position(
+        &self,
+        position: usize,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> Option<&Workspace>;
 
-    fn get_second_wp_with_output(&self, output: &str) -> Option<&Workspace>;
+    fn unpinned_titles(&self, separator: &str, pins: &Pins) -> Vec<&str>;
+    fn unpinned_group(
+        &self,
+        title: &str,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+    ) -> (usize, Vec<&str>);
+    fn next_unused_number(
+        &self,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> usize;
 
-    fn next_unused_number(&self) -> usize;
+    fn name_for_title(
+        &self,
+        title: &str,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> String;
 }
 
 impl WorkspacesExt for Workspaces {
-    fn fixup_wps(&self) -> Vec<String> {
-        let mut cmds: Vec<String> = vec![];
-        for (i, wp) in self.workspaces.iter().enumerate() {
-            let old_num = if wp.num >= 0 { wp.num as usize } else { 0 };
-            let new_num = i + 1;
-            if old_num != new_num {
-                cmds.push(format!(
-                    "rename workspace \"{}\" to \"{}:{}\"",
-                    wp.name,
-                    new_num,
-                    wp.title()
-                ));
+    fn fixup_wps(&self, separator: &str, pins: &Pins, numbering: Numbering) -> Vec<Command> {
+        number_groups(self, separator, pins, numbering)
+            .into_iter()
+            .flat_map(|(first, group)| renumber(group, first, separator, pins))
+            .collect()
+    }
+
+    // put the unpinned workspace with the given title at the given place among the unpinned
+    // workspaces numbered together with it (0 is the first), shifting the ones in between along
+    fn move_wp(
+        &self,
+        title: &str,
+        index: usize,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+    ) -> Vec<Command> {
+        let mut cmds: Vec<Command> = vec![];
+        for (first, group) in number_groups(self, separator, pins, numbering) {
+            let (pinned, mut unpinned): (Vec<&Workspace>, Vec<&Workspace>) = group
+                .into_iter()
+                .partition(|wp| pins.contains_key(wp.title(separator)));
+            if let Some(from) = unpinned.iter().position(|wp| wp.title(separator) == title) {
+                let wp = unpinned.remove(from);
+                let to = index.min(unpinned.len());
+                unpinned.insert(to, wp);
             }
+            cmds.extend(renumber(
+                pinned.into_iter().chain(unpinned),
+                first,
+                separator,
+                pins,
+            ));
         }
         cmds
     }
 
-    fn choices_str(&self) -> String {
-        let mut numbered_titles: Vec<(usize, &str)> = self
-            .workspaces
-            .iter()
-            .map(|wp| (wp.num as usize, wp.title()))
-            .collect();
-        numbered_titles.sort_unstable_by(|a, b| (&a.0).cmp(&b.0));
-        numbered_titles
-            .iter()
-            .map(|t| &*t.1)
-            .collect::<Vec<&str>>()
-            .join("\n")
+    // the workspaces in the given order; with an output, "last-used" goes by the focus history of
+    // that output alone
+    fn sorted_wps(
+        &self,
+        order: Order,
+        history: &History,
+        separator: &str,
+        output: Option<&str>,
+    ) -> Vec<&Workspace> {
+        let mut wps: Vec<&Workspace> = self.workspaces.iter().collect();
+        // numbers repeat when every output is numbered from 1; those stay in output order
+        wps.sort_by_key(|wp| wp.num);
+        // the sorts below are stable, so ties stay in number order
+        match order {
+            Order::Default => (),
+            Order::LastUsed => {
+                // workspaces missing from the history go at the end
+                let last_used = match output {
+                    Some(output) => history.last_used_on(output),
+                    None => history.last_used(),
+                };
+                wps.sort_by_key(|wp| {
+                    last_used
+                        .iter()
+                        .position(|title| title == wp.title(separator))
+                        .unwrap_or(last_used.len())
+                });
+            }
+            Order::Alphabetical => wps.sort_by_key(|wp| wp.title(separator).to_lowercase()),
+            Order::Frequency => {
+                wps.sort_by_key(|wp| Reverse(history.times_chosen(wp.title(separator))))
+            }
+        }
+        wps
     }
 
     fn get_wp_with_focus(&self) -> Option<&Workspace> {
-        self.workspaces.iter().find(|wp| wp.focused == true)
+        self.workspaces.iter().find(|wp| wp.focused)
     }
 
     fn get_wp_with_name(&self, name: &str) -> Option<&Workspace> {
@@ -80,48 +198,505 @@ impl WorkspacesExt for Workspaces {
     }
 
     fn get_wp_with_number(&self, number: usize) -> Option<&Workspace> {
-        self.workspaces.iter().find(|wp| wp.num == number as i32)
+        // compared as usize, so large numbers can't wrap around onto small ones
+        self.workspaces
+            .iter()
+            .find(|wp| wp.num >= 0 && wp.num as usize == number)
     }
 
-    fn get_wp_with_title(&self, title: &str) -> Option<&Workspace> {
-        self.workspaces.iter().find(|wp| wp.title() == title)
+    fn get_wp_with_title(&self, title: &str, separator: &str) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|wp| wp.title(separator) == title)
     }
 
-    fn next_unused_number(&self) -> usize {
-        self.workspaces.len() + 1
+    // the workspace with the given number, counting from the first number of the given output's
+    // workspaces with per-output numbering (so 1 is the first on every output)
+    fn get_wp_at_position(
+        &self,
+        position: usize,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> Option<&Workspace> {
+        match numbering {
+            Numbering::Global => self.get_wp_with_number(position),
+            Numbering::PerOutput { .. } => number_groups(self, separator, pins, numbering)
+                .into_iter()
+                .find(|g| g.1[0].output == output)
+                .and_then(|(first, group)| {
+                    let number = (first - 1).checked_add(position)?;
+                    group
+                        .into_iter()
+                        .find(|wp| wp.num >= 0 && wp.num as usize == number)
+                }),
+        }
     }
 
-    fn get_second_wp_with_output(&self, output: &str) -> Option<&Workspace> {
+    // titles of the workspaces that aren't pinned, in order
+    fn unpinned_titles(&self, separator: &str, pins: &Pins) -> Vec<&str> {
         self.workspaces
             .iter()
-            .filter(|wp| wp.output == output)
-            .nth(1)
+            .map(|wp| wp.title(separator))
+            .filter(|title| !pins.contains_key(*title))
+            .collect()
+    }
+
+    // the first number of the workspaces numbered together with the one with the given title, and
+    // the titles of the unpinned ones among them, in order
+    fn unpinned_group(
+        &self,
+        title: &str,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+    ) -> (usize, Vec<&str>) {
+        let groups = number_groups(self, separator, pins, numbering);
+        let (first, group) = match groups
+            .iter()
+            .find(|g| g.1.iter().any(|wp| wp.title(separator) == title))
+        {
+            Some(&(first, ref group)) => (first, group),
+            None => return (1, vec![]),
+        };
+        let titles = group
+            .iter()
+            .map(|wp| wp.title(separator))
+            .filter(|title| !pins.contains_key(*title))
+            .collect();
+        (first, titles)
+    }
+
+    // the number a new unpinned workspace on the given output gets when it's added after all the
+    // others numbered together with it
+    fn next_unused_number(
+        &self,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> usize {
+        let (first, unpinned) = match numbering {
+            Numbering::Global => (1, self.unpinned_titles(separator, pins).len()),
+            Numbering::PerOutput { step } => {
+                let groups = number_groups(self, separator, pins, numbering);
+                match groups.iter().find(|g| g.1[0].output == output) {
+                    Some(&(first, ref group)) => (first, count_unpinned(group, separator, pins)),
+                    // an output without workspaces comes after the others
+                    None => {
+                        let next = match groups.last() {
+                            Some(&(first, ref group)) => {
+                                number_after_group(first, group, separator, pins)
+                            }
+                            None => 1,
+                        };
+                        (group_start(groups.len(), step, next), 0)
+                    }
+                }
+            }
+        };
+        unpinned_numbers(pins, first).nth(unpinned).unwrap()
+    }
+
+    // the name to use when targeting the workspace with the given title: either the name of the
+    // existing workspace with that title, or the combination of its pinned number (or the next
+    // unused number) and the title itself (which makes i3 create the workspace)
+    fn name_for_title(
+        &self,
+        title: &str,
+        separator: &str,
+        pins: &Pins,
+        numbering: Numbering,
+        output: &str,
+    ) -> String {
+        match (self.get_wp_with_title(title, separator), pins.get(title)) {
+            (Some(wp), _) => wp.name.clone(),
+            (None, Some(number)) => format!("{}{}{}", number, separator, title),
+            (None, None) => format!(
+                "{}{}{}",
+                self.next_unused_number(separator, pins, numbering, output),
+                separator,
+                title
+            ),
+        }
     }
 }
 
 pub trait WorkspaceExt {
-    fn promote(&self) -> String;
-    fn title<'a>(&'a self) -> &'a str;
+    fn promote(&self, separator: &str, pins: &Pins) -> Option<Command>;
+    fn rename(&self, title: &str, separator: &str) -> Command;
+    fn title(&self, separator: &str) -> &str;
 }
 
 impl WorkspaceExt for Workspace {
-    fn promote(&self) -> String {
-        format!(
-            "rename workspace \"{}\" to \"0:{}\"",
-            self.name,
-            self.title()
-        )
+    // move the workspace in front of all others by giving it number 0, which the next fixup turns
+    // into the first unpinned number; pinned workspaces stay where they are
+    fn promote(&self, separator: &str, pins: &Pins) -> Option<Command> {
+        if pins.contains_key(self.title(separator)) {
+            return None;
+        }
+        Some(Command::RenameWorkspace {
+            from: self.name.clone(),
+            to: format!("0{}{}", separator, self.title(separator)),
+        })
+    }
+
+    // keep the number, if the workspace has one, and replace the title
+    fn rename(&self, title: &str, separator: &str) -> Command {
+        let to = if self.num >= 0 {
+            format!("{}{}{}", self.num, separator, title)
+        } else {
+            title.to_owned()
+        };
+        Command::RenameWorkspace {
+            from: self.name.clone(),
+            to,
+        }
     }
 
-    fn title<'a>(&'a self) -> &'a str {
-        title_from_name(&self.name).unwrap()
+    fn title(&self, separator: &str) -> &str {
+        title_from_name(&self.name, separator).unwrap()
     }
 }
 
-pub fn go_to(name: &str) -> String {
-    format!("workspace \"{}\"", name)
+// numbers from `first` on that aren't pinned to any title, in increasing order
+pub fn unpinned_numbers(pins: &Pins, first: usize) -> impl Iterator<Item = usize> + '_ {
+    (first..).filter(move |n| !pins.values().any(|p| p == n))
 }
 
-pub fn send_to(name: &str) -> String {
-    format!("move container to workspace \"{}\"", name)
+// the workspaces that are numbered together, in order, each group with its first number
+fn number_groups<'a>(
+    workspaces: &'a Workspaces,
+    separator: &str,
+    pins: &Pins,
+    numbering: Numbering,
+) -> Vec<(usize, Vec<&'a Workspace>)> {
+    let step = match numbering {
+        Numbering::Global => return vec![(1, workspaces.workspaces.iter().collect())],
+        Numbering::PerOutput { step } => step,
+    };
+
+    // a workspace covers its output, so the outputs are ordered by where their workspaces are;
+    // the sort is stable, so outputs at the same place keep the window manager's order
+    let mut groups: Vec<Vec<&Workspace>> = vec![];
+    for wp in &workspaces.workspaces {
+        match groups.iter_mut().find(|group| group[0].output == wp.output) {
+            Some(group) => group.push(wp),
+            None => groups.push(vec![wp]),
+        }
+    }
+    groups.sort_by_key(|group| (group[0].rect.0, group[0].rect.1));
+
+    let mut next = 1;
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(n, group)| {
+            let first = group_start(n, step, next);
+            next = number_after_group(first, &group, separator, pins);
+            (first, group)
+        })
+        .collect()
+}
+
+// the first number of the nth output's group: n * step + 1, unless the output to its left has
+// more workspaces than the step leaves room for, in which case it starts at `next`, right after
+// them, so no two outputs share a number. with a step of 0 every output starts at 1 on purpose
+fn group_start(n: usize, step: usize, next: usize) -> usize {
+    match step {
+        0 => 1,
+        step => (n * step + 1).max(next),
+    }
+}
+
+// the first unpinned number after the ones the unpinned workspaces of a group get
+fn number_after_group(first: usize, group: &[&Workspace], separator: &str, pins: &Pins) -> usize {
+    unpinned_numbers(pins, first)
+        .nth(count_unpinned(group, separator, pins))
+        .unwrap()
+}
+
+fn count_unpinned(group: &[&Workspace], separator: &str, pins: &Pins) -> usize {
+    group
+        .iter()
+        .filter(|wp| !pins.contains_key(wp.title(separator)))
+        .count()
+}
+
+// rename the workspaces so that pinned titles get their own numbers and the others are numbered in
+// the given order from `first` on, skipping the pinned numbers
+fn renumber<'a, I>(order: I, first: usize, separator: &str, pins: &Pins) -> Vec<Command>
+where
+    I: IntoIterator<Item = &'a Workspace>,
+{
+    let mut cmds: Vec<Command> = vec![];
+    let mut numbers = unpinned_numbers(pins, first);
+    for wp in order {
+        let old_num = if wp.num >= 0 { wp.num as usize } else { 0 };
+        let new_num = match pins.get(wp.title(separator)) {
+            Some(&number) => number,
+            None => numbers.next().unwrap(),
+        };
+        // workspaces whose name is just a number keep a name that is just their number, rather
+        // than becoming "3:5"; a number the user chose as the title ("3:5") is kept
+        let new_name = if wp.name == wp.title(separator) && wp.num >= 0 {
+            new_num.to_string()
+        } else {
+            format!("{}{}{}", new_num, separator, wp.title(separator))
+        };
+        // names that are just a number are left alone as long as the number is right; any
+        // other name is rewritten if it doesn't use the current separator
+        let misformatted = wp.name != new_name && wp.name != wp.title(separator);
+        if old_num != new_num || misformatted {
+            cmds.push(Command::RenameWorkspace {
+                from: wp.name.clone(),
+                to: new_name,
+            });
+        }
+    }
+    cmds
+}
+
+pub fn go_to(name: &str) -> Command {
+    Command::Workspace(name.to_owned())
+}
+
+pub fn send_to(name: &str) -> Command {
+    Command::MoveToWorkspace(name.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Numbering, Pins, WorkspaceExt, WorkspacesExt};
+    use command::Command;
+    use i3ipc::reply::{Workspace, Workspaces};
+
+    fn wps(names: &[(&str, &str)]) -> Workspaces {
+        Workspaces {
+            workspaces: names
+                .iter()
+                .map(|&(name, output)| Workspace {
+                    num: name
+                        .split(|c: char| !c.is_ascii_digit())
+                        .next()
+                        .unwrap()
+                        .parse()
+                        .unwrap_or(-1),
+                    name: name.to_owned(),
+                    visible: false,
+                    focused: false,
+                    urgent: false,
+                    rect: (0, 0, 0, 0),
+                    output: output.to_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    fn rendered(cmds: Vec<Command>) -> Vec<String> {
+        cmds.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn fixup_closes_gaps() {
+        let workspaces = wps(&[("0:web", "a"), ("1:mail", "a"), ("4:rust", "a")]);
+        assert_eq!(
+            rendered(workspaces.fixup_wps(":", &Pins::new(), Numbering::Global)),
+            vec![
+                "rename workspace \"0:web\" to \"1:web\"",
+                "rename workspace \"1:mail\" to \"2:mail\"",
+                "rename workspace \"4:rust\" to \"3:rust\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn fixup_keeps_bare_numbers_bare() {
+        // "3:7" was titled 7 on purpose, so it stays as it is
+        let workspaces = wps(&[("1:mail", "a"), ("5", "a"), ("3:7", "a")]);
+        assert_eq!(
+            rendered(workspaces.fixup_wps(":", &Pins::new(), Numbering::Global)),
+            vec!["rename workspace \"5\" to \"2\""]
+        );
+    }
+
+    #[test]
+    fn fixup_leaves_correct_names_alone() {
+        let workspaces = wps(&[("1:web", "a"), ("2", "a"), ("3:rust", "a")]);
+        assert!(workspaces
+            .fixup_wps(":", &Pins::new(), Numbering::Global)
+            .is_empty());
+    }
+
+    #[test]
+    fn fixup_rewrites_other_separators() {
+        let workspaces = wps(&[("1:web", "a"), ("2 | mail", "a")]);
+        assert_eq!(
+            rendered(workspaces.fixup_wps(" | ", &Pins::new(), Numbering::Global)),
+            vec!["rename workspace \"1:web\" to \"1 | web\""]
+        );
+    }
+
+    #[test]
+    fn promote_moves_to_number_zero() {
+        let workspaces = wps(&[("3:rust", "a")]);
+        assert_eq!(
+            workspaces.workspaces[0]
+                .promote(":", &Pins::new())
+                .unwrap()
+                .to_string(),
+            "rename workspace \"3:rust\" to \"0:rust\""
+        );
+        assert!(workspaces.workspaces[0]
+            .promote(":", &pins(&[("rust", 3)]))
+            .is_none());
+    }
+
+    fn pins(pins: &[(&str, usize)]) -> Pins {
+        pins.iter().map(|&(t, n)| (t.to_owned(), n)).collect()
+    }
+
+    #[test]
+    fn fixup_respects_pins() {
+        let workspaces = wps(&[("0:web", "a"), ("1:rust", "a"), ("3:mail", "a")]);
+        assert_eq!(
+            rendered(workspaces.fixup_wps(
+                ":",
+                &pins(&[("mail", 1), ("chat", 2)]),
+                Numbering::Global
+            )),
+            vec![
+                "rename workspace \"0:web\" to \"3:web\"",
+                "rename workspace \"1:rust\" to \"4:rust\"",
+                "rename workspace \"3:mail\" to \"1:mail\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn new_workspaces_skip_pinned_numbers() {
+        let pins = pins(&[("mail", 2)]);
+        let workspaces = wps(&[("1:web", "a"), ("2:mail", "a")]);
+        assert_eq!(
+            workspaces.next_unused_number(":", &pins, Numbering::Global, "a"),
+            3
+        );
+        assert_eq!(
+            workspaces.name_for_title("rust", ":", &pins, Numbering::Global, "a"),
+            "3:rust"
+        );
+        let workspaces = wps(&[("1:web", "a")]);
+        assert_eq!(
+            workspaces.name_for_title("mail", ":", &pins, Numbering::Global, "a"),
+            "2:mail"
+        );
+        assert_eq!(
+            workspaces.name_for_title("rust", ":", &pins, Numbering::Global, "a"),
+            "3:rust"
+        );
+        // with per-output numbering, the numbers come from the range of the output
+        let workspaces = wps(&[("1:web", "a"), ("11:chat", "b"), ("12:irc", "b")]);
+        let per_output = Numbering::PerOutput { step: 10 };
+        assert_eq!(
+            workspaces.name_for_title("rust", ":", &pins, per_output, "a"),
+            "3:rust"
+        );
+        assert_eq!(
+            workspaces.name_for_title("rust", ":", &pins, per_output, "b"),
+            "13:rust"
+        );
+        assert_eq!(
+            workspaces.name_for_title("rust", ":", &pins, per_output, "c"),
+            "21:rust"
+        );
+    }
+
+    #[test]
+    fn move_wp_shifts_the_others() {
+        let workspaces = wps(&[("1:a", "x"), ("2:mail", "x"), ("3:b", "x"), ("4:c", "x")]);
+        let pins = pins(&[("mail", 2)]);
+        assert_eq!(
+            rendered(workspaces.move_wp("c", 0, ":", &pins, Numbering::Global)),
+            vec![
+                "rename workspace \"4:c\" to \"1:c\"",
+                "rename workspace \"1:a\" to \"3:a\"",
+                "rename workspace \"3:b\" to \"4:b\"",
+            ]
+        );
+        // positions past the end are clamped
+        assert_eq!(
+            rendered(workspaces.move_wp("a", 10, ":", &pins, Numbering::Global)),
+            vec![
+                "rename workspace \"3:b\" to \"1:b\"",
+                "rename workspace \"4:c\" to \"3:c\"",
+                "rename workspace \"1:a\" to \"4:a\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn fixup_numbers_each_output_separately() {
+        let mut workspaces = wps(&[
+            ("1:web", "right"),
+            ("4:chat", "right"),
+            ("2:mail", "left"),
+            ("3:rust", "left"),
+        ]);
+        // the outputs are taken from left to right, whatever order they're listed in
+        for wp in &mut workspaces.workspaces {
+            if wp.output == "right" {
+                wp.rect = (1920, 0, 1920, 1080);
+            }
+        }
+
+        assert_eq!(
+            rendered(workspaces.fixup_wps(":", &Pins::new(), Numbering::PerOutput { step: 0 })),
+            vec![
+                "rename workspace \"2:mail\" to \"1:mail\"",
+                "rename workspace \"3:rust\" to \"2:rust\"",
+                "rename workspace \"4:chat\" to \"2:chat\"",
+            ]
+        );
+        assert_eq!(
+            rendered(workspaces.fixup_wps(
+                ":",
+                &pins(&[("mail", 1)]),
+                Numbering::PerOutput { step: 10 }
+            )),
+            vec![
+                "rename workspace \"2:mail\" to \"1:mail\"",
+                "rename workspace \"3:rust\" to \"2:rust\"",
+                "rename workspace \"1:web\" to \"11:web\"",
+                "rename workspace \"4:chat\" to \"12:chat\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn crowded_outputs_push_the_next_ones_along() {
+        let mut workspaces = wps(&[
+            ("1:a", "left"),
+            ("2:b", "left"),
+            ("3:c", "left"),
+            ("3:x", "right"),
+        ]);
+        workspaces.workspaces[3].rect = (1920, 0, 1920, 1080);
+        let numbering = Numbering::PerOutput { step: 2 };
+
+        // the right output would start at 3, which the left one already uses
+        assert_eq!(
+            rendered(workspaces.fixup_wps(":", &Pins::new(), numbering)),
+            vec!["rename workspace \"3:x\" to \"4:x\""]
+        );
+        // pinned numbers are skipped there too
+        assert_eq!(
+            rendered(workspaces.fixup_wps(":", &pins(&[("z", 4)]), numbering)),
+            vec!["rename workspace \"3:x\" to \"5:x\""]
+        );
+        assert_eq!(
+            workspaces.next_unused_number(":", &Pins::new(), numbering, "elsewhere"),
+            5
+        );
+    }
 }
@@ -0,0 +1,218 @@
+use failure::err_msg;
+use failure::Error;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+// names accepted by `menu_from_name`, in the order they should be offered to users
+pub const MENU_NAMES: &[&str] = &["dmenu", "rofi", "fzf", "wofi", "bemenu"];
+
+// something that can show the user a list of workspace titles and let them pick one (or type a
+// new one)
+pub trait Menu {
+    // present the newline-separated choices and return the trimmed line the user picked; returns
+    // None if the user dismissed the menu without choosing anything
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error>;
+}
+
+// each menu carries extra arguments that are passed to the program after the ones fluidspaces
+// needs to run it in dmenu mode
+
+pub struct Dmenu {
+    pub args: Vec<String>,
+}
+
+impl Menu for Dmenu {
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error> {
+        run_stdio_menu("dmenu", &[], &self.args, choices)
+    }
+}
+
+pub struct Rofi {
+    pub args: Vec<String>,
+}
+
+impl Menu for Rofi {
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error> {
+        run_stdio_menu("rofi", &["-dmenu"], &self.args, choices)
+    }
+}
+
+pub struct Wofi {
+    pub args: Vec<String>,
+}
+
+impl Menu for Wofi {
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error> {
+        run_stdio_menu("wofi", &["--dmenu"], &self.args, choices)
+    }
+}
+
+pub struct Bemenu {
+    pub args: Vec<String>,
+}
+
+impl Menu for Bemenu {
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error> {
+        run_stdio_menu("bemenu", &[], &self.args, choices)
+    }
+}
+
+// fzf needs a terminal to draw in, and the daemon doesn't have one, so it gets run inside a new
+// terminal window; the choices and the selection are passed through temporary files because the
+// terminal doesn't forward its stdin/stdout to the program it runs. the files live in a directory
+// only the current user can get into, so nobody else can read them, swap them for symlinks or
+// plant a selection
+pub struct Fzf {
+    pub terminal: String,
+    pub args: Vec<String>,
+}
+
+impl Fzf {
+    // use the terminal named by $TERMINAL, or xterm if that isn't set
+    pub fn new(args: Vec<String>) -> Fzf {
+        Fzf {
+            terminal: env::var("TERMINAL").unwrap_or_else(|_| String::from("xterm")),
+            args,
+        }
+    }
+}
+
+impl Menu for Fzf {
+    fn choose(&self, choices: &str) -> Result<Option<String>, Error> {
+        let dir = private_dir()?;
+        let choices_path = dir.join("choices");
+        let choice_path = dir.join("choice");
+
+        if let Err(e) = fs::write(&choices_path, choices) {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(e.into());
+        }
+
+        // the file paths and extra arguments are passed as positional parameters so they never
+        // need quoting
+        let status = Command::new(&self.terminal)
+            .arg("-e")
+            .arg("sh")
+            .arg("-c")
+            .arg("i=\"$1\" o=\"$2\"; shift 2; fzf --print-query \"$@\" < \"$i\" | tail -n 1 > \"$o\"")
+            .arg("sh")
+            .arg(&choices_path)
+            .arg(&choice_path)
+            .args(&self.args)
+            .status();
+
+        let choice = fs::read_to_string(&choice_path).unwrap_or_default();
+
+        // clean up before reporting any failure to launch the terminal
+        let _ = fs::remove_dir_all(&dir);
+
+        if let Err(e) = status {
+            return Err(err_msg(format!(
+                "Couldn't launch terminal {:?} for fzf: {}",
+                self.terminal, e
+            )));
+        }
+
+        Ok(non_empty(&choice))
+    }
+}
+
+// create a new directory that only the current user can access, in the runtime directory if there
+// is one; creating it fails if anything already exists at the path, so another user can't have
+// prepared it
+fn private_dir() -> Result<PathBuf, Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = match env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(ref dir) if dir.is_absolute() => dir.clone(),
+        _ => env::temp_dir(),
+    };
+    loop {
+        let dir = parent.join(format!(
+            "fluidspaces-fzf-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            // someone else got there first; try the next name
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(err_msg(format!(
+                    "Couldn't create a directory for fzf in {:?}: {}",
+                    parent, e
+                )))
+            }
+        }
+    }
+}
+
+// look up a menu implementation by the name a user would type on the command line, giving it
+// extra arguments for the menu program
+pub fn menu_from_name(name: &str, args: &[String]) -> Result<Box<dyn Menu>, Error> {
+    let args = args.to_vec();
+    match name {
+        "dmenu" => Ok(Box::new(Dmenu { args })),
+        "rofi" => Ok(Box::new(Rofi { args })),
+        "fzf" => Ok(Box::new(Fzf::new(args))),
+        "wofi" => Ok(Box::new(Wofi { args })),
+        "bemenu" => Ok(Box::new(Bemenu { args })),
+        name => Err(err_msg(format!("Unknown menu program: {:?}", name))),
+    }
+}
+
+// run a dmenu-compatible program: choices are written to its stdin and the selection is read
+// back from its stdout
+fn run_stdio_menu(
+    program: &str,
+    args: &[&str],
+    extra_args: &[String],
+    choices: &str,
+) -> Result<Option<String>, Error> {
+    // spawn the menu process
+    let mut menu_proc = match Command::new(program)
+        .args(args)
+        .args(extra_args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(p) => p,
+        Err(e) => return Err(err_msg(format!("Couldn't launch {}: {}", program, e))),
+    };
+
+    {
+        // get a ref to stdin of the menu process
+        let stdin = match menu_proc.stdin.as_mut() {
+            Some(s) => s,
+            None => return Err(err_msg(format!("Couldn't get ref to stdin of {}", program))),
+        };
+
+        // write the list of workspaces to the menu's stdin
+        stdin.write_all(choices.as_bytes())?;
+    }
+
+    // wait for the menu to exit and collect all output on its stdout; the exit status is ignored
+    // because most of these programs exit non-zero when the user dismisses them
+    let menu_output = menu_proc.wait_with_output()?;
+
+    Ok(non_empty(&String::from_utf8_lossy(&menu_output.stdout[..])))
+}
+
+// trim the raw output of a menu; an empty selection means the user didn't choose anything
+fn non_empty(raw: &str) -> Option<String> {
+    match raw.trim() {
+        "" => None,
+        choice => Some(choice.to_owned()),
+    }
+}
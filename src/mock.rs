@@ -0,0 +1,424 @@
+use failure::Error;
+
+use i3ipc::reply::Output;
+use i3ipc::reply::Outputs;
+use i3ipc::reply::Workspace;
+use i3ipc::reply::Workspaces;
+
+use std::iter;
+
+use command::Command;
+use wm::Events;
+use wm::Window;
+use wm::WindowManager;
+
+// an in-memory stand-in for i3 that understands the commands fluidspaces sends, so whole actions
+// can be tested without a running window manager
+pub struct MockWm {
+    // in the order i3 lists them: grouped by output, numbered workspaces first and in order
+    workspaces: Vec<MockWorkspace>,
+    outputs: Vec<String>,
+    focused: String,
+    // container id given to the next window that opens
+    next_id: i64,
+    // every command that was run, rendered the way the window manager would receive it
+    pub commands: Vec<String>,
+}
+
+struct MockWorkspace {
+    name: String,
+    output: String,
+    // the last window has focus when the workspace is focused
+    windows: Vec<MockWindow>,
+}
+
+// the name doubles as the window's class and title
+struct MockWindow {
+    id: i64,
+    name: String,
+}
+
+impl MockWm {
+    // workspaces are given as (name, output, windows); the first one is focused
+    pub fn new(workspaces: &[(&str, &str, &[&str])]) -> MockWm {
+        let mut wm = MockWm {
+            workspaces: vec![],
+            outputs: vec![],
+            focused: workspaces[0].0.to_owned(),
+            next_id: 1,
+            commands: vec![],
+        };
+        for &(name, output, windows) in workspaces {
+            wm.create(name, output);
+            for window in windows {
+                wm.open(name, window);
+            }
+        }
+        wm
+    }
+
+    // names of all workspaces, in i3's order
+    pub fn names(&self) -> Vec<&str> {
+        self.workspaces.iter().map(|wp| wp.name.as_str()).collect()
+    }
+
+    pub fn focused(&self) -> &str {
+        &self.focused
+    }
+
+    pub fn windows(&self, name: &str) -> Vec<&str> {
+        match self.workspaces.iter().find(|wp| wp.name == name) {
+            Some(wp) => wp.windows.iter().map(|w| w.name.as_str()).collect(),
+            None => panic!("no workspace named {:?}", name),
+        }
+    }
+
+    pub fn output(&self, name: &str) -> &str {
+        match self.workspaces.iter().find(|wp| wp.name == name) {
+            Some(wp) => &wp.output,
+            None => panic!("no workspace named {:?}", name),
+        }
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.workspaces.iter().any(|wp| wp.name == name)
+    }
+
+    fn workspace_mut(&mut self, name: &str) -> &mut MockWorkspace {
+        self.workspaces
+            .iter_mut()
+            .find(|wp| wp.name == name)
+            .unwrap()
+    }
+
+    fn open(&mut self, workspace: &str, window: &str) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.workspace_mut(workspace).windows.push(MockWindow {
+            id,
+            name: window.to_owned(),
+        });
+    }
+
+    fn create(&mut self, name: &str, output: &str) {
+        if !self.outputs.iter().any(|o| o == output) {
+            self.outputs.push(output.to_owned());
+        }
+        self.workspaces.push(MockWorkspace {
+            name: name.to_owned(),
+            output: output.to_owned(),
+            windows: vec![],
+        });
+        self.sort();
+    }
+
+    // i3 keeps the workspaces on each output sorted by number, with unnumbered ones at the end
+    fn sort(&mut self) {
+        let outputs = &self.outputs;
+        self.workspaces.sort_by_key(|wp| {
+            let num = number(&wp.name);
+            (outputs.iter().position(|o| *o == wp.output), num < 0, num)
+        });
+    }
+
+    fn focused_output(&self) -> String {
+        match self.workspaces.iter().find(|wp| wp.name == self.focused) {
+            Some(wp) => wp.output.clone(),
+            None => String::from("eDP-1"),
+        }
+    }
+
+    fn run(&mut self, cmd: &str) {
+        if let Some(rest) = cmd.strip_prefix("workspace ") {
+            let (name, _) = quoted(rest);
+            self.go_to(&name);
+        } else if let Some(rest) = cmd.strip_prefix("move container to workspace ") {
+            let (name, _) = quoted(rest);
+            self.send_to(&name);
+        } else if let Some(rest) = cmd.strip_prefix("rename workspace ") {
+            let (old, rest) = quoted(rest);
+            let rest = match rest.strip_prefix(" to ") {
+                Some(rest) => rest,
+                None => panic!("malformed rename: {:?}", cmd),
+            };
+            let (new, _) = quoted(rest);
+            self.rename(&old, &new);
+        } else if let Some(rest) = cmd.strip_prefix("[workspace=") {
+            let (pattern, rest) = quoted(rest);
+            if let Some(rest) = rest.strip_prefix("] move container to workspace ") {
+                let (name, _) = quoted(rest);
+                self.move_contents(&literal(&pattern), &name);
+            } else if let Some(rest) = rest.strip_prefix("] move workspace to output ") {
+                let (output, _) = quoted(rest);
+                self.move_to_output(&literal(&pattern), &output, false);
+            } else {
+                panic!("unsupported command: {:?}", cmd);
+            }
+        } else if let Some(rest) = cmd.strip_prefix("move workspace to output ") {
+            let (output, _) = quoted(rest);
+            let focused = self.focused.clone();
+            self.move_to_output(&focused, &output, true);
+        } else if cmd.starts_with("append_layout ") {
+            // layouts only add placeholders, which the mock doesn't keep track of
+        } else if let Some(rest) = cmd.strip_prefix("exec --no-startup-id ") {
+            // the program's window opens on the focused workspace, named after the command
+            let (program, _) = quoted(rest);
+            let focused = self.focused.clone();
+            self.open(&focused, &program);
+        } else if let Some(rest) = cmd.strip_prefix("[con_id=") {
+            let (id, rest) = rest.split_at(rest.find(']').unwrap());
+            let name = match rest.strip_prefix("] move container to workspace ") {
+                Some(rest) => quoted(rest).0,
+                None => panic!("unsupported command: {:?}", cmd),
+            };
+            self.move_window(id.parse().unwrap(), &name);
+        } else if let Some(rest) = cmd.strip_prefix("focus output ") {
+            let (output, _) = quoted(rest);
+            self.focus_output(&output);
+        } else {
+            panic!("unsupported command: {:?}", cmd);
+        }
+    }
+
+    fn go_to(&mut self, name: &str) {
+        if self.focused == name {
+            return;
+        }
+        if !self.exists(name) {
+            let output = self.focused_output();
+            self.create(name, &output);
+        }
+        // i3 closes empty workspaces once they lose focus
+        let old = self.focused.clone();
+        self.workspaces
+            .retain(|wp| wp.name != old || !wp.windows.is_empty());
+        self.focused = name.to_owned();
+    }
+
+    fn send_to(&mut self, name: &str) {
+        let focused = self.focused.clone();
+        if focused == name {
+            return;
+        }
+        let window = match self.workspace_mut(&focused).windows.pop() {
+            Some(window) => window,
+            // there is nothing to move out of an empty workspace
+            None => return,
+        };
+        if !self.exists(name) {
+            let output = self.focused_output();
+            self.create(name, &output);
+        }
+        self.workspace_mut(name).windows.push(window);
+    }
+
+    fn move_contents(&mut self, from: &str, to: &str) {
+        if from == to || !self.exists(from) {
+            return;
+        }
+        let windows: Vec<MockWindow> = self.workspace_mut(from).windows.drain(..).collect();
+        if windows.is_empty() {
+            return;
+        }
+        if !self.exists(to) {
+            let output = self.focused_output();
+            self.create(to, &output);
+        }
+        self.workspace_mut(to).windows.extend(windows);
+        // like i3, close the emptied workspace unless it's the focused one
+        if self.focused != from {
+            self.workspaces.retain(|wp| wp.name != from);
+        }
+    }
+
+    fn move_window(&mut self, id: i64, to: &str) {
+        let from = match self
+            .workspaces
+            .iter()
+            .find(|wp| wp.windows.iter().any(|w| w.id == id))
+        {
+            Some(wp) => wp.name.clone(),
+            // criteria that match nothing are silently ignored
+            None => return,
+        };
+        if from == to {
+            return;
+        }
+        let windows = &mut self.workspace_mut(&from).windows;
+        let window = windows.remove(windows.iter().position(|w| w.id == id).unwrap());
+        if !self.exists(to) {
+            let output = self.focused_output();
+            self.create(to, &output);
+        }
+        self.workspace_mut(to).windows.push(window);
+        if self.focused != from && self.workspace_mut(&from).windows.is_empty() {
+            self.workspaces.retain(|wp| wp.name != from);
+        }
+    }
+
+    // the mock doesn't remember which workspace is visible on each output, so the first one there
+    // gets focus
+    fn focus_output(&mut self, output: &str) {
+        match self.workspaces.iter().find(|wp| wp.output == output) {
+            Some(wp) => self.focused = wp.name.clone(),
+            None => panic!("no workspace on output {:?}", output),
+        }
+    }
+
+    // criteria only match workspaces with windows on them, the focused workspace can be moved
+    // either way
+    fn move_to_output(&mut self, name: &str, output: &str, focused: bool) {
+        if !self.exists(name) || (!focused && self.workspace_mut(name).windows.is_empty()) {
+            return;
+        }
+        if !self.outputs.iter().any(|o| o == output) {
+            self.outputs.push(output.to_owned());
+        }
+        self.workspace_mut(name).output = output.to_owned();
+        self.sort();
+    }
+
+    fn rename(&mut self, old: &str, new: &str) {
+        assert!(self.exists(old), "renaming missing workspace {:?}", old);
+        assert!(
+            old == new || !self.exists(new),
+            "renaming {:?} to existing workspace {:?}",
+            old,
+            new
+        );
+        self.workspace_mut(old).name = new.to_owned();
+        if self.focused == old {
+            self.focused = new.to_owned();
+        }
+        self.sort();
+    }
+}
+
+impl WindowManager for MockWm {
+    fn get_workspaces(&mut self) -> Result<Workspaces, Error> {
+        let focused_output = self.focused_output();
+        Ok(Workspaces {
+            workspaces: self
+                .workspaces
+                .iter()
+                .map(|wp| Workspace {
+                    num: number(&wp.name),
+                    name: wp.name.clone(),
+                    // one workspace per output is visible; good enough to tell the focused
+                    // output's apart from the others
+                    visible: wp.name == self.focused || wp.output != focused_output,
+                    focused: wp.name == self.focused,
+                    urgent: false,
+                    rect: (0, 0, 0, 0),
+                    output: wp.output.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    // every output that has a workspace on it is active, and shows its first workspace unless the
+    // focused one is there
+    fn get_outputs(&mut self) -> Result<Outputs, Error> {
+        let focused_output = self.focused_output();
+        Ok(Outputs {
+            outputs: self
+                .outputs
+                .iter()
+                .map(|output| Output {
+                    name: output.clone(),
+                    active: true,
+                    primary: false,
+                    current_workspace: if *output == focused_output {
+                        Some(self.focused.clone())
+                    } else {
+                        self.workspaces
+                            .iter()
+                            .find(|wp| wp.output == *output)
+                            .map(|wp| wp.name.clone())
+                    },
+                    rect: (0, 0, 0, 0),
+                })
+                .collect(),
+        })
+    }
+
+    fn get_windows(&mut self) -> Result<Vec<Window>, Error> {
+        Ok(self
+            .workspaces
+            .iter()
+            .flat_map(|wp| {
+                wp.windows.iter().map(move |w| Window {
+                    id: w.id,
+                    workspace: wp.name.clone(),
+                    class: Some(w.name.clone()),
+                    instance: None,
+                    title: Some(w.name.clone()),
+                })
+            })
+            .collect())
+    }
+
+    fn run_commands(&mut self, cmds: &[Command]) -> Result<(), Error> {
+        // commands are interpreted from their rendered form so that quoting gets tested too
+        for cmd in cmds {
+            let cmd = cmd.to_string();
+            self.run(&cmd);
+            self.commands.push(cmd);
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<Events, Error> {
+        Ok(Box::new(iter::empty()))
+    }
+}
+
+// the number i3 gives a workspace: its leading digits, or -1 if there are none
+fn number(name: &str) -> i32 {
+    let digits: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().unwrap_or(-1)
+}
+
+// the name matched by a criterion of the form ^name$ whose special characters are escaped
+fn literal(pattern: &str) -> String {
+    let pattern = match pattern.strip_prefix('^').and_then(|p| p.strip_suffix('$')) {
+        Some(pattern) => pattern,
+        None => panic!("unsupported criterion: {:?}", pattern),
+    };
+    let mut name = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => name.extend(chars.next()),
+            c => name.push(c),
+        }
+    }
+    name
+}
+
+// split a double-quoted string off the front of a command, unescaping it the way i3 does: only
+// \" and \\ are escapes, any other backslash is kept
+fn quoted(s: &str) -> (String, &str) {
+    let mut chars = s.char_indices().peekable();
+    assert_eq!(
+        chars.next(),
+        Some((0, '"')),
+        "expected a quoted string: {:?}",
+        s
+    );
+    let mut unquoted = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (unquoted, &s[i + 1..]),
+            '\\' => match chars.peek() {
+                Some(&(_, c)) if c == '"' || c == '\\' => {
+                    unquoted.push(c);
+                    chars.next();
+                }
+                _ => unquoted.push('\\'),
+            },
+            c => unquoted.push(c),
+        }
+    }
+    panic!("unterminated quoted string: {:?}", s);
+}
@@ -4,7 +4,7 @@ use failure::err_msg;
 use failure::Error;
 
 use nom::types::CompleteStr as Input;
-use nom::{digit, rest};
+use nom::{digit, rest, IResult};
 
 use std::str::from_utf8;
 
@@ -17,24 +17,31 @@ named!(colon<Input, Input>, tag!(":"));
 // parser matches all input until the end; outputs everything matched
 named!(the_rest<Input, Input>, call!(rest));
 
-// parser returns tuple of (number, name), both are optional
-named!(title_parser<Input, (Option<Input>, Option<Input>)>,
-    do_parse!(
+// parser returns tuple of (number, name), both are optional; the separator between them is either
+// the given one or a colon, so names created before the separator was changed still parse
+fn title_parser<'a>(
+    input: Input<'a>,
+    separator: &str,
+) -> IResult<Input<'a>, (Option<Input<'a>>, Option<Input<'a>>)> {
+    // whitespace around the separator is already skipped by ws!, so a separator like " | " has to
+    // be matched without it
+    let separator = separator.trim();
+    do_parse!(input,
         num: opt!(ws!(number)) >>
-        opt!(ws!(colon)) >>
+        opt!(ws!(alt!(tag!(separator) | colon))) >>
         name: opt!(ws!(the_rest)) >>
         (num, match name {
             Some(Input("")) => None,
             _ => name
         })
     )
-);
+}
 
 // public interface of the parser - give it the i3-formatted name of the
-// workspace and it tries to give you back a useful title (without numbers, if
-// possible)
-pub fn title_from_name<'a>(name: &'a str) -> Result<&'a str, Error> {
-    match title_parser(Input(name)) {
+// workspace and the separator used between number and title, and it tries to
+// give you back a useful title (without numbers, if possible)
+pub fn title_from_name<'a>(name: &'a str, separator: &str) -> Result<&'a str, Error> {
+    match title_parser(Input(name), separator) {
         Ok((_, (number, name))) => Ok(from_utf8(name.or(number).unwrap().as_bytes())?),
         Err(e) => Err(err_msg(format!(
             "Couldn't parse title from name {:?}: {:?}",
@@ -118,7 +125,7 @@ mod tests {
             #[test]
             fn $name() {
                 let (input, exp_out): (&str, (Option<Input>, Option<Input>)) = $value;
-                match title_parser(Input(input)) {
+                match title_parser(Input(input), ":") {
                     Ok((_, act_out)) => assert_eq!(act_out, exp_out),
                     Err(_) => {
                         // assert_eq!(
@@ -154,5 +161,36 @@ mod tests {
             number_title_with_missing_index_present_colon: (":2", (None, Some(Input("2")))),
             number_title_with_missing_index_missing_colon: ("2", (Some(Input("2")), None)),
         }
+
+        mod custom_separator_tests {
+            use super::*;
+
+            // define macro that will generate tests for title_parser with a non-colon separator
+            macro_rules! separator_tests {(
+                $($name:ident: $value:expr,)*
+            ) => {$(
+
+                #[test]
+                fn $name() {
+                    let (input, sep, exp_out): (&str, &str, (Option<Input>, Option<Input>)) = $value;
+                    match title_parser(Input(input), sep) {
+                        Ok((_, act_out)) => assert_eq!(act_out, exp_out),
+                        Err(_) => panic!("Parser gave an Err() but test expects an Ok()"),
+                    }
+                }
+
+            )*}}
+
+            separator_tests! {
+                basic: ("7|foo", "|", (Some(Input("7")), Some(Input("foo")))),
+                multichar: ("7 - foo", "-", (Some(Input("7")), Some(Input("foo")))),
+                padded: ("7 | foo", " | ", (Some(Input("7")), Some(Input("foo")))),
+                box_drawing: ("7│foo", "│", (Some(Input("7")), Some(Input("foo")))),
+                colon_still_accepted: ("7:foo", "|", (Some(Input("7")), Some(Input("foo")))),
+                colon_kept_in_title: ("7|foo:bar", "|", (Some(Input("7")), Some(Input("foo:bar")))),
+                separator_kept_in_title: ("7|foo|bar", "|", (Some(Input("7")), Some(Input("foo|bar")))),
+                missing_separator: ("foo", "|", (None, Some(Input("foo")))),
+            }
+        }
     }
 }
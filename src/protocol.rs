@@ -0,0 +1,357 @@
+use failure::err_msg;
+use failure::Error;
+
+use serde_json;
+use serde_json::Value;
+
+use history::Order;
+
+use std::fmt;
+use std::str::FromStr;
+
+// version of the wire format spoken between fluidspaces-msg and the daemon; bump this whenever a
+// change would make an older peer misinterpret a message
+pub const PROTOCOL_VERSION: u64 = 1;
+
+// names accepted by `Action::from_str`, in the order they should be offered to users
+pub const ACTION_NAMES: &[&str] = &[
+    "go_to",
+    "send_to",
+    "bring_to",
+    "go_to_index",
+    "send_to_index",
+    "bring_to_index",
+    "toggle",
+    "next",
+    "prev",
+    "rename",
+    "merge",
+    "move_up",
+    "move_down",
+    "move_to",
+    "move_to_output",
+    "restore",
+    "reload",
+];
+
+// flags the daemon knows how to honour: "current_output" lists only the workspaces on the focused
+// output in the menu, and "show_outputs" adds the output of each workspace to its entry
+pub const FLAG_NAMES: &[&str] = &["current_output", "show_outputs"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    GoTo,
+    SendTo,
+    BringTo,
+    GoToIndex,
+    SendToIndex,
+    BringToIndex,
+    Toggle,
+    Next,
+    Prev,
+    Rename,
+    Merge,
+    MoveUp,
+    MoveDown,
+    MoveTo,
+    MoveToOutput,
+    Restore,
+    Reload,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Action::GoTo => "go_to",
+            Action::SendTo => "send_to",
+            Action::BringTo => "bring_to",
+            Action::GoToIndex => "go_to_index",
+            Action::SendToIndex => "send_to_index",
+            Action::BringToIndex => "bring_to_index",
+            Action::Toggle => "toggle",
+            Action::Next => "next",
+            Action::Prev => "prev",
+            Action::Rename => "rename",
+            Action::Merge => "merge",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveTo => "move_to",
+            Action::MoveToOutput => "move_to_output",
+            Action::Restore => "restore",
+            Action::Reload => "reload",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Action, Error> {
+        match s {
+            "go_to" => Ok(Action::GoTo),
+            "send_to" => Ok(Action::SendTo),
+            "bring_to" => Ok(Action::BringTo),
+            "go_to_index" => Ok(Action::GoToIndex),
+            "send_to_index" => Ok(Action::SendToIndex),
+            "bring_to_index" => Ok(Action::BringToIndex),
+            "toggle" => Ok(Action::Toggle),
+            "next" => Ok(Action::Next),
+            "prev" => Ok(Action::Prev),
+            "rename" => Ok(Action::Rename),
+            "merge" => Ok(Action::Merge),
+            "move_up" => Ok(Action::MoveUp),
+            "move_down" => Ok(Action::MoveDown),
+            "move_to" => Ok(Action::MoveTo),
+            "move_to_output" => Ok(Action::MoveToOutput),
+            "restore" => Ok(Action::Restore),
+            "reload" => Ok(Action::Reload),
+            s => Err(err_msg(format!("Unknown action: {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// a single request sent by fluidspaces-msg; travels over the socket as one line of JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Request {
+    pub version: u64,
+    pub action: Action,
+    // title of the workspace to act on
+    #[serde(default)]
+    pub target: Option<String>,
+    // title of the workspace whose windows are moved, for actions that take them from somewhere
+    // other than the focused workspace
+    #[serde(default)]
+    pub source: Option<String>,
+    // workspace number to move to, for move_to, or of the workspace to act on, for the *_index
+    // actions
+    #[serde(default)]
+    pub position: Option<usize>,
+    // name of the menu program used to ask the user for a target
+    #[serde(default)]
+    pub menu: Option<String>,
+    // name of the output (monitor) to act on; new workspaces are created there, and move_to_output
+    // moves the workspace there
+    #[serde(default)]
+    pub output: Option<String>,
+    // how to arrange the workspaces in the menu
+    #[serde(default)]
+    pub order: Option<Order>,
+    // boolean options; the daemon rejects flags it doesn't know about
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl Request {
+    // build a request for the given action with every optional field left empty
+    pub fn new(action: Action) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            action,
+            target: None,
+            source: None,
+            position: None,
+            menu: None,
+            output: None,
+            order: None,
+            flags: vec![],
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    // serialize the request as a single newline-terminated line of JSON
+    pub fn to_line(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)? + "\n")
+    }
+
+    // parse a line received from a client, checking the protocol version before anything else so
+    // that outdated clients get a meaningful error instead of a confusing parse failure
+    pub fn from_line(line: &str) -> Result<Request, Error> {
+        let value: Value = match serde_json::from_str(line.trim()) {
+            Ok(value) => value,
+            // clients older than the JSON protocol sent the bare action name
+            Err(_) => return Err(unsupported_version("0")),
+        };
+
+        match value.get("version") {
+            Some(version) if version.as_u64() == Some(PROTOCOL_VERSION) => (),
+            Some(version) => return Err(unsupported_version(&version.to_string())),
+            None => return Err(err_msg("Request is missing a protocol version")),
+        }
+
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => return Err(err_msg(format!("Couldn't parse request: {}", e))),
+        };
+
+        if let Some(flag) = request
+            .flags
+            .iter()
+            .find(|f| !FLAG_NAMES.contains(&f.as_str()))
+        {
+            return Err(err_msg(format!("Unknown flag: {:?}", flag)));
+        }
+
+        Ok(request)
+    }
+}
+
+// the daemon's answer to a request; also travels over the socket as one line of JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    // the action was carried out; `focused` is the name of the workspace that has focus afterwards
+    Success { focused: Option<String> },
+    // the user dismissed the menu, so nothing was done
+    Cancelled,
+    // the action failed
+    Error { message: String },
+}
+
+impl Response {
+    // exit status fluidspaces-msg should report for this response
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Response::Success { .. } => 0,
+            Response::Error { .. } => 1,
+            Response::Cancelled => 2,
+        }
+    }
+
+    // serialize the response as a single newline-terminated line of JSON
+    pub fn to_line(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)? + "\n")
+    }
+
+    // parse a line received from the daemon
+    pub fn from_line(line: &str) -> Result<Response, Error> {
+        match serde_json::from_str(line.trim()) {
+            Ok(response) => Ok(response),
+            Err(e) => Err(err_msg(format!("Couldn't parse reply from daemon: {}", e))),
+        }
+    }
+}
+
+fn unsupported_version(version: &str) -> Error {
+    err_msg(format!(
+        "Unsupported protocol version {} (the daemon speaks version {}); make sure fluidspaces-msg \
+         and the fluidspaces daemon are the same release",
+        version, PROTOCOL_VERSION
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Request, Response, ACTION_NAMES, PROTOCOL_VERSION};
+    use history::Order;
+
+    #[test]
+    fn action_names_round_trip() {
+        for name in ACTION_NAMES {
+            assert_eq!(name.parse::<Action>().unwrap().as_str(), *name);
+        }
+    }
+
+    #[test]
+    fn request_round_trips() {
+        let mut request = Request::new(Action::BringTo);
+        request.target = Some(String::from("mail"));
+        request.source = Some(String::from("inbox"));
+        request.position = Some(3);
+        request.menu = Some(String::from("rofi"));
+        request.output = Some(String::from("HDMI-1"));
+        request.order = Some(Order::LastUsed);
+        request.flags = vec![String::from("show_outputs")];
+
+        let line = request.to_line().unwrap();
+        assert!(line.ends_with('\n'));
+        assert_eq!(line.matches('\n').count(), 1);
+        assert_eq!(Request::from_line(&line).unwrap(), request);
+    }
+
+    #[test]
+    fn optional_fields_can_be_omitted() {
+        let line = format!("{{\"version\":{},\"action\":\"toggle\"}}", PROTOCOL_VERSION);
+        assert_eq!(
+            Request::from_line(&line).unwrap(),
+            Request::new(Action::Toggle)
+        );
+    }
+
+    #[test]
+    fn bare_action_is_unsupported_version() {
+        let e = Request::from_line("go_to").unwrap_err();
+        assert!(e.to_string().contains("Unsupported protocol version 0"));
+    }
+
+    #[test]
+    fn future_version_is_unsupported_version() {
+        let line = "{\"version\":9999,\"action\":\"teleport\"}";
+        let e = Request::from_line(line).unwrap_err();
+        assert!(e.to_string().contains("Unsupported protocol version 9999"));
+    }
+
+    #[test]
+    fn missing_version_is_rejected() {
+        let e = Request::from_line("{\"action\":\"go_to\"}").unwrap_err();
+        assert!(e.to_string().contains("missing a protocol version"));
+    }
+
+    #[test]
+    fn unknown_flag_is_rejected() {
+        let mut request = Request::new(Action::GoTo);
+        request.flags.push(String::from("no_such_flag"));
+        let e = Request::from_line(&request.to_line().unwrap()).unwrap_err();
+        assert!(e.to_string().contains("Unknown flag"));
+    }
+
+    #[test]
+    fn unknown_action_is_rejected() {
+        let line = format!(
+            "{{\"version\":{},\"action\":\"teleport\"}}",
+            PROTOCOL_VERSION
+        );
+        assert!(Request::from_line(&line).is_err());
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = vec![
+            Response::Success {
+                focused: Some(String::from("1:mail")),
+            },
+            Response::Success { focused: None },
+            Response::Cancelled,
+            Response::Error {
+                message: String::from("Couldn't find a focused workspace"),
+            },
+        ];
+        for response in responses {
+            let line = response.to_line().unwrap();
+            assert_eq!(line.matches('\n').count(), 1);
+            assert_eq!(Response::from_line(&line).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn only_success_exits_zero() {
+        assert_eq!(Response::Success { focused: None }.exit_code(), 0);
+        assert_ne!(Response::Cancelled.exit_code(), 0);
+        assert_ne!(
+            Response::Error {
+                message: String::new()
+            }
+            .exit_code(),
+            0
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use failure::err_msg;
+use failure::Error;
+
+use i3ipc::reply::Workspaces;
+
+use serde_json;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use wm::Window;
+use WorkspaceExt;
+
+// what the workspaces looked like the last time the daemon saved them, so that titles and the
+// windows on them can be brought back after the window manager restarts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    // in the order the window manager listed them
+    pub workspaces: Vec<SavedWorkspace>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SavedWorkspace {
+    pub name: String,
+    pub title: String,
+    // -1 for workspaces without a number
+    pub number: i32,
+    pub output: String,
+    pub windows: Vec<SavedWindow>,
+}
+
+// window ids don't survive a restart, so windows are recognised by what they say about themselves
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SavedWindow {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+}
+
+impl SavedWindow {
+    pub fn from_window(window: &Window) -> SavedWindow {
+        SavedWindow {
+            class: window.class.clone(),
+            instance: window.instance.clone(),
+            title: window.title.clone(),
+        }
+    }
+}
+
+impl Session {
+    // $XDG_STATE_HOME/fluidspaces/session.json, falling back to ~/.local/state when
+    // XDG_STATE_HOME isn't set
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = match env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+            Some(ref dir) if dir.is_absolute() => dir.clone(),
+            _ => PathBuf::from(env::var_os("HOME")?)
+                .join(".local")
+                .join("state"),
+        };
+        Some(state_home.join("fluidspaces").join("session.json"))
+    }
+
+    // describe the given workspaces and the windows on them
+    pub fn capture(workspaces: &Workspaces, windows: &[Window], separator: &str) -> Session {
+        Session {
+            workspaces: workspaces
+                .workspaces
+                .iter()
+                .map(|wp| SavedWorkspace {
+                    name: wp.name.clone(),
+                    title: wp.title(separator).to_owned(),
+                    number: wp.num,
+                    output: wp.output.clone(),
+                    windows: windows
+                        .iter()
+                        .filter(|w| w.workspace == wp.name)
+                        .map(SavedWindow::from_window)
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    // read the session saved at the given path; there is nothing to restore if it doesn't exist
+    pub fn load(path: &Path) -> Result<Option<Session>, Error> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(err_msg(format!("Couldn't read session {:?}: {}", path, e))),
+        };
+
+        match serde_json::from_slice(&contents) {
+            Ok(session) => Ok(Some(session)),
+            Err(e) => Err(err_msg(format!("Invalid session {:?}: {}", path, e))),
+        }
+    }
+
+    // write the session to the given path; it goes to a temporary file first so that a crash
+    // halfway through can't leave a truncated session behind
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        match fs::rename(&tmp_path, path) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(err_msg(format!("Couldn't save session {:?}: {}", path, e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SavedWindow, SavedWorkspace, Session};
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn sessions_survive_a_round_trip() {
+        let session = Session {
+            workspaces: vec![SavedWorkspace {
+                name: String::from("1:web"),
+                title: String::from("web"),
+                number: 1,
+                output: String::from("eDP-1"),
+                windows: vec![SavedWindow {
+                    class: Some(String::from("firefox")),
+                    instance: Some(String::from("Navigator")),
+                    title: None,
+                }],
+            }],
+        };
+        let dir = env::temp_dir().join(format!("fluidspaces-session-test-{}", process::id()));
+        let path = dir.join("state").join("session.json");
+
+        assert_eq!(Session::load(&path).unwrap(), None);
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap(), Some(session));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use failure::err_msg;
+use failure::Error;
+
+use libc;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+// the socket the daemon listens on when no --socket option is given; lives in the user's runtime
+// directory and is named after the display so every X or Wayland session can have its own daemon
+// (sway sessions often have an X display for Xwayland too, so the Wayland one wins)
+pub fn default_socket_path() -> PathBuf {
+    socket_path_for(
+        env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from),
+        env::var("WAYLAND_DISPLAY")
+            .or_else(|_| env::var("DISPLAY"))
+            .ok(),
+        unsafe { libc::getuid() },
+    )
+}
+
+// build the default socket path from its ingredients; if there is no usable runtime directory,
+// fall back to a private per-user directory inside /tmp
+fn socket_path_for(runtime_dir: Option<PathBuf>, display: Option<String>, uid: u32) -> PathBuf {
+    let dir = match runtime_dir {
+        Some(ref dir) if dir.is_absolute() => dir.clone(),
+        _ => fallback_dir(uid),
+    };
+
+    dir.join(format!(
+        "fluidspaces-{}.sock",
+        session_identity(display.as_deref())
+    ))
+}
+
+// the directory in /tmp that holds the socket when there is no runtime directory
+fn fallback_dir(uid: u32) -> PathBuf {
+    env::temp_dir().join(format!("fluidspaces-{}", uid))
+}
+
+// turn a display name like ":0", "localhost:10.0" or "wayland-1" into something that is safe to put in a file
+// name
+fn session_identity(display: Option<&str>) -> String {
+    match display.map(|d| d.trim_start_matches(':')) {
+        Some(display) if !display.is_empty() => display
+            .chars()
+            .map(|c| match c {
+                '.' | '-' | '_' => c,
+                c if c.is_ascii_alphanumeric() => c,
+                _ => '_',
+            })
+            .collect(),
+        _ => String::from("default"),
+    }
+}
+
+// make sure the directory that will hold the socket exists; a missing directory is created private
+// to the current user. the /tmp fallback directory has a predictable name, so if it already exists
+// but is owned by someone else (or readable by others) it is refused rather than silently used.
+// directories the user chose are trusted, since the socket itself is restricted anyway
+pub fn prepare_socket_dir(socket_path: &Path) -> Result<(), Error> {
+    let dir = match socket_path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => return Ok(()),
+    };
+
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        return Ok(());
+    }
+
+    let uid = unsafe { libc::getuid() };
+    if dir != fallback_dir(uid) {
+        return Ok(());
+    }
+
+    // symlinks aren't followed, so the check is about the directory itself
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(err_msg(format!(
+            "Refusing to use socket directory {:?}: it must be owned by the current user and \
+             inaccessible to everyone else",
+            dir
+        )));
+    }
+
+    Ok(())
+}
+
+// restrict a freshly bound socket to the current user
+pub fn restrict_socket(socket_path: &Path) -> Result<(), Error> {
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prepare_socket_dir, session_identity, socket_path_for};
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::process;
+
+    #[test]
+    fn uses_runtime_dir_and_display() {
+        assert_eq!(
+            socket_path_for(
+                Some(PathBuf::from("/run/user/1000")),
+                Some(String::from(":1")),
+                1000
+            ),
+            PathBuf::from("/run/user/1000/fluidspaces-1.sock")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_private_temp_dir() {
+        assert_eq!(
+            socket_path_for(None, Some(String::from(":0")), 1000),
+            env::temp_dir().join("fluidspaces-1000/fluidspaces-0.sock")
+        );
+        assert_eq!(
+            socket_path_for(Some(PathBuf::from("relative")), None, 1000),
+            env::temp_dir().join("fluidspaces-1000/fluidspaces-default.sock")
+        );
+    }
+
+    #[test]
+    fn chosen_directories_are_trusted() {
+        let dir = env::temp_dir().join(format!("fluidspaces-socket-test-{}", process::id()));
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(prepare_socket_dir(&dir.join("fs.sock")).is_ok());
+
+        // missing directories are created private
+        let nested = dir.join("nested");
+        prepare_socket_dir(&nested.join("fs.sock")).unwrap();
+        assert_eq!(
+            fs::metadata(&nested).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn display_is_sanitized() {
+        assert_eq!(session_identity(Some(":0")), "0");
+        assert_eq!(session_identity(Some(":0.1")), "0.1");
+        assert_eq!(session_identity(Some("localhost:10.0")), "localhost_10.0");
+        assert_eq!(session_identity(Some("wayland-1")), "wayland-1");
+        assert_eq!(session_identity(Some("../../x")), ".._.._x");
+        assert_eq!(session_identity(Some("")), "default");
+        assert_eq!(session_identity(None), "default");
+    }
+}
@@ -0,0 +1,520 @@
+use failure::err_msg;
+use failure::Error;
+
+use i3ipc::reply::Output;
+use i3ipc::reply::Outputs;
+use i3ipc::reply::Workspace;
+use i3ipc::reply::Workspaces;
+
+use serde_json;
+use serde_json::Value;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use command::Command;
+use ipc::IpcSocket;
+use ipc::GET_OUTPUTS;
+use ipc::GET_TREE;
+use ipc::GET_WORKSPACES;
+use ipc::RUN_COMMAND;
+use ipc::SUBSCRIBE;
+use ipc::WINDOW_EVENT;
+use ipc::WORKSPACE_EVENT;
+
+// names accepted by `WmKind::from_str`, in the order they should be offered to users
+pub const WM_NAMES: &[&str] = &["i3", "sway"];
+
+// the window managers fluidspaces can drive
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WmKind {
+    I3,
+    Sway,
+}
+
+impl WmKind {
+    // sway sets SWAYSOCK for everything it starts, so its presence means we're running under sway
+    pub fn detect() -> WmKind {
+        match env::var_os("SWAYSOCK") {
+            Some(_) => WmKind::Sway,
+            None => WmKind::I3,
+        }
+    }
+}
+
+impl FromStr for WmKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<WmKind, Error> {
+        match s {
+            "i3" => Ok(WmKind::I3),
+            "sway" => Ok(WmKind::Sway),
+            s => Err(err_msg(format!("Unknown window manager: {:?}", s))),
+        }
+    }
+}
+
+// what happened to a workspace, as reported by the window manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceChange {
+    Focus,
+    Init,
+    Empty,
+    Urgent,
+    Rename,
+    Reload,
+    Restored,
+    Move,
+    Unknown,
+}
+
+// what happened to a window, as reported by the window manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowChange {
+    New,
+    Close,
+    Focus,
+    Title,
+    Move,
+    Unknown,
+}
+
+// the things a window manager reports that the daemon reacts to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WmEvent {
+    // a workspace was created, focused, renamed, emptied, ...
+    Workspace(WorkspaceChange),
+    // a window was opened, closed, moved, ...; carries the window's container id
+    Window(WindowChange, i64),
+}
+
+// a window and the name of the workspace it is on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    // the container id, used to pick the window out with con_id criteria
+    pub id: i64,
+    pub workspace: String,
+    // the X11 class, or the app_id of native wayland windows on sway
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+}
+
+// events from a window manager, in the order they happened; the iterator ends once the
+// connection is lost
+pub type Events = Box<dyn Iterator<Item = Result<WmEvent, Error>> + Send>;
+
+// everything fluidspaces needs from the window manager
+pub trait WindowManager {
+    fn get_workspaces(&mut self) -> Result<Workspaces, Error>;
+    fn get_outputs(&mut self) -> Result<Outputs, Error>;
+    // every window on a workspace, in the order the tree lists them
+    fn get_windows(&mut self) -> Result<Vec<Window>, Error>;
+    fn run_commands(&mut self, cmds: &[Command]) -> Result<(), Error>;
+    // open a separate connection that reports workspace and window events
+    fn subscribe(&mut self) -> Result<Events, Error>;
+}
+
+// connect to the given window manager, or to the one we're running under
+pub fn connect(kind: Option<WmKind>) -> Result<Box<dyn WindowManager>, Error> {
+    Ok(Box::new(Connection::open(
+        kind.unwrap_or_else(WmKind::detect),
+    )?))
+}
+
+// the socket path is taken from the environment variable the window manager sets, or asked from
+// the window manager itself
+fn socket_path(var: &str, program: &str) -> Result<PathBuf, Error> {
+    if let Some(path) = env::var_os(var) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let output = match process::Command::new(program)
+        .arg("--get-socketpath")
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            return Err(err_msg(format!(
+                "{} isn't set and {} couldn't be asked for its socket: {}",
+                var, program, e
+            )))
+        }
+    };
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "" => Err(err_msg(format!(
+            "{} isn't set and {} didn't report its socket",
+            var, program
+        ))),
+        path => Ok(PathBuf::from(path)),
+    }
+}
+
+// a connection to i3 or sway; sway implements i3's IPC protocol, so the only difference is where
+// the socket is found
+pub struct Connection {
+    socket_path: PathBuf,
+    ipc: IpcSocket,
+}
+
+impl Connection {
+    pub fn open(kind: WmKind) -> Result<Connection, Error> {
+        let socket_path = match kind {
+            WmKind::I3 => socket_path("I3SOCK", "i3")?,
+            WmKind::Sway => socket_path("SWAYSOCK", "sway")?,
+        };
+        let ipc = IpcSocket::connect(&socket_path)?;
+        Ok(Connection { socket_path, ipc })
+    }
+}
+
+impl WindowManager for Connection {
+    fn get_workspaces(&mut self) -> Result<Workspaces, Error> {
+        workspaces_from_json(&self.ipc.request(GET_WORKSPACES, "")?)
+    }
+
+    fn get_outputs(&mut self) -> Result<Outputs, Error> {
+        outputs_from_json(&self.ipc.request(GET_OUTPUTS, "")?)
+    }
+
+    fn get_windows(&mut self) -> Result<Vec<Window>, Error> {
+        let mut windows = vec![];
+        windows_from_json(&self.ipc.request(GET_TREE, "")?, None, &mut windows);
+        Ok(windows)
+    }
+
+    fn run_commands(&mut self, cmds: &[Command]) -> Result<(), Error> {
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        let cmds: Vec<String> = cmds.iter().map(|c| c.to_string()).collect();
+        cmds.iter().for_each(|c| println!("`{}`", c));
+
+        // a command that fails doesn't stop the others, so failures are only reported
+        let reply = self.ipc.request(RUN_COMMAND, &cmds.join("; "))?;
+        for outcome in reply.as_array().into_iter().flatten() {
+            if outcome.get("success").and_then(Value::as_bool) != Some(true) {
+                eprintln!(
+                    "Command failed: {}",
+                    outcome
+                        .get("error")
+                        .and_then(Value::as_str)
+                        .unwrap_or("unknown error")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<Events, Error> {
+        // events get a connection of their own so they can't get mixed up with replies
+        let mut ipc = IpcSocket::connect(&self.socket_path)?;
+        let reply = ipc.request(SUBSCRIBE, "[\"workspace\", \"window\"]")?;
+        if reply.get("success").and_then(Value::as_bool) != Some(true) {
+            return Err(err_msg("Couldn't subscribe to workspace and window events"));
+        }
+        Ok(Box::new(EventStream { ipc, done: false }))
+    }
+}
+
+struct EventStream {
+    ipc: IpcSocket,
+    done: bool,
+}
+
+impl Iterator for EventStream {
+    type Item = Result<WmEvent, Error>;
+
+    fn next(&mut self) -> Option<Result<WmEvent, Error>> {
+        if self.done {
+            return None;
+        }
+        loop {
+            match self.ipc.receive() {
+                Ok((WORKSPACE_EVENT, payload)) => return Some(workspace_event_from_json(&payload)),
+                Ok((WINDOW_EVENT, payload)) => return Some(window_event_from_json(&payload)),
+                // events we didn't subscribe to
+                Ok(_) => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(err_msg(format!(
+                        "Lost connection to the window manager: {}",
+                        e
+                    ))));
+                }
+            }
+        }
+    }
+}
+
+fn workspaces_from_json(reply: &Value) -> Result<Workspaces, Error> {
+    let workspaces = match reply.as_array() {
+        Some(workspaces) => workspaces,
+        None => return Err(err_msg("Expected a list of workspaces")),
+    };
+    match workspaces.iter().map(workspace_from_json).collect() {
+        Some(workspaces) => Ok(Workspaces { workspaces }),
+        None => Err(err_msg(format!("Malformed list of workspaces: {}", reply))),
+    }
+}
+
+fn workspace_from_json(wp: &Value) -> Option<Workspace> {
+    let rect = wp.get("rect")?;
+    let int = |v: &Value, key: &str| v.get(key).and_then(Value::as_i64).map(|i| i as i32);
+    Some(Workspace {
+        num: int(wp, "num")?,
+        name: wp.get("name")?.as_str()?.to_owned(),
+        visible: wp.get("visible")?.as_bool()?,
+        focused: wp.get("focused")?.as_bool()?,
+        urgent: wp.get("urgent")?.as_bool()?,
+        rect: (
+            int(rect, "x")?,
+            int(rect, "y")?,
+            int(rect, "width")?,
+            int(rect, "height")?,
+        ),
+        output: wp.get("output")?.as_str()?.to_owned(),
+    })
+}
+
+fn outputs_from_json(reply: &Value) -> Result<Outputs, Error> {
+    let outputs = match reply.as_array() {
+        Some(outputs) => outputs,
+        None => return Err(err_msg("Expected a list of outputs")),
+    };
+    match outputs.iter().map(output_from_json).collect() {
+        Some(outputs) => Ok(Outputs { outputs }),
+        None => Err(err_msg(format!("Malformed list of outputs: {}", reply))),
+    }
+}
+
+fn output_from_json(output: &Value) -> Option<Output> {
+    let rect = output.get("rect")?;
+    let int = |v: &Value, key: &str| v.get(key).and_then(Value::as_i64).map(|i| i as i32);
+    Some(Output {
+        name: output.get("name")?.as_str()?.to_owned(),
+        active: output.get("active")?.as_bool()?,
+        // sway leaves this out for outputs that are turned off
+        primary: output
+            .get("primary")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        current_workspace: output
+            .get("current_workspace")
+            .and_then(Value::as_str)
+            .map(String::from),
+        rect: (
+            int(rect, "x")?,
+            int(rect, "y")?,
+            int(rect, "width")?,
+            int(rect, "height")?,
+        ),
+    })
+}
+
+// collect the windows below a node of the tree; `workspace` is the name of the workspace the node
+// is on, if any. the scratchpad is a hidden workspace whose name starts with "__", and its windows
+// aren't on any workspace the user can see
+fn windows_from_json(node: &Value, workspace: Option<&str>, windows: &mut Vec<Window>) {
+    let name = node.get("name").and_then(Value::as_str);
+    let workspace = match node.get("type").and_then(Value::as_str) {
+        Some("workspace") => match name {
+            Some(name) if name.starts_with("__") => return,
+            name => name,
+        },
+        _ => workspace,
+    };
+
+    let props = node.get("window_properties");
+    let prop = |key: &str| {
+        props
+            .and_then(|p| p.get(key))
+            .and_then(Value::as_str)
+            .map(String::from)
+    };
+    // X11 windows have a window id, native wayland windows on sway an app_id
+    let app_id = node.get("app_id").and_then(Value::as_str);
+    let is_window = node.get("window").is_some_and(|w| !w.is_null()) || app_id.is_some();
+    if let (true, Some(workspace), Some(id)) =
+        (is_window, workspace, node.get("id").and_then(Value::as_i64))
+    {
+        windows.push(Window {
+            id,
+            workspace: workspace.to_owned(),
+            class: prop("class").or_else(|| app_id.map(String::from)),
+            instance: prop("instance"),
+            title: name.map(String::from),
+        });
+    }
+
+    for key in &["nodes", "floating_nodes"] {
+        for child in node
+            .get(*key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            windows_from_json(child, workspace, windows);
+        }
+    }
+}
+
+fn window_event_from_json(payload: &[u8]) -> Result<WmEvent, Error> {
+    let event: Value = serde_json::from_slice(payload)?;
+    let change = match event.get("change").and_then(Value::as_str) {
+        Some("new") => WindowChange::New,
+        Some("close") => WindowChange::Close,
+        Some("focus") => WindowChange::Focus,
+        Some("title") => WindowChange::Title,
+        Some("move") => WindowChange::Move,
+        Some(_) => WindowChange::Unknown,
+        None => return Err(err_msg("Window event without a change")),
+    };
+    match event.pointer("/container/id").and_then(Value::as_i64) {
+        Some(id) => Ok(WmEvent::Window(change, id)),
+        None => Err(err_msg("Window event without a container id")),
+    }
+}
+
+fn workspace_event_from_json(payload: &[u8]) -> Result<WmEvent, Error> {
+    let event: Value = serde_json::from_slice(payload)?;
+    let change = match event.get("change").and_then(Value::as_str) {
+        Some("focus") => WorkspaceChange::Focus,
+        Some("init") => WorkspaceChange::Init,
+        Some("empty") => WorkspaceChange::Empty,
+        Some("urgent") => WorkspaceChange::Urgent,
+        Some("rename") => WorkspaceChange::Rename,
+        Some("reload") => WorkspaceChange::Reload,
+        Some("restored") => WorkspaceChange::Restored,
+        Some("move") => WorkspaceChange::Move,
+        Some(_) => WorkspaceChange::Unknown,
+        None => return Err(err_msg("Workspace event without a change")),
+    };
+    Ok(WmEvent::Workspace(change))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        outputs_from_json, window_event_from_json, windows_from_json, workspace_event_from_json,
+        workspaces_from_json, Window, WindowChange, WmEvent, WmKind, WorkspaceChange,
+    };
+    use serde_json;
+
+    #[test]
+    fn workspaces_are_decoded() {
+        let reply = serde_json::from_str(
+            r#"[{"id": 7, "num": 1, "name": "1:mail", "visible": true, "focused": true,
+                 "urgent": false, "output": "eDP-1", "representation": "H[firefox]",
+                 "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}}]"#,
+        )
+        .unwrap();
+        let workspaces = workspaces_from_json(&reply).unwrap().workspaces;
+        assert_eq!(workspaces.len(), 1);
+        assert_eq!(workspaces[0].num, 1);
+        assert_eq!(workspaces[0].name, "1:mail");
+        assert!(workspaces[0].focused);
+        assert_eq!(workspaces[0].rect, (0, 0, 1920, 1080));
+        assert_eq!(workspaces[0].output, "eDP-1");
+    }
+
+    #[test]
+    fn malformed_workspaces_are_rejected() {
+        let reply = serde_json::from_str(r#"[{"num": 1, "name": "1:mail"}]"#).unwrap();
+        assert!(workspaces_from_json(&reply).is_err());
+        assert!(workspaces_from_json(&serde_json::Value::Null).is_err());
+    }
+
+    #[test]
+    fn outputs_are_decoded() {
+        let reply = serde_json::from_str(
+            r#"[{"name": "eDP-1", "active": true, "primary": true, "current_workspace": "1:mail",
+                 "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+                {"name": "xroot-0", "active": false, "primary": false, "current_workspace": null,
+                 "rect": {"x": 0, "y": 0, "width": 3840, "height": 1080}}]"#,
+        )
+        .unwrap();
+        let outputs = outputs_from_json(&reply).unwrap().outputs;
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].name, "eDP-1");
+        assert_eq!(outputs[0].current_workspace, Some(String::from("1:mail")));
+        assert!(!outputs[1].active);
+        assert_eq!(outputs[1].current_workspace, None);
+    }
+
+    #[test]
+    fn workspace_events_are_decoded() {
+        assert_eq!(
+            workspace_event_from_json(br#"{"change": "empty", "current": null}"#).unwrap(),
+            WmEvent::Workspace(WorkspaceChange::Empty)
+        );
+        assert_eq!(
+            workspace_event_from_json(br#"{"change": "wobble"}"#).unwrap(),
+            WmEvent::Workspace(WorkspaceChange::Unknown)
+        );
+        assert!(workspace_event_from_json(b"{}").is_err());
+    }
+
+    #[test]
+    fn windows_are_found_in_tree() {
+        let tree = serde_json::from_str(
+            r#"{"id": 1, "type": "root", "name": "root", "window": null, "nodes": [
+                 {"id": 2, "type": "output", "name": "eDP-1", "window": null, "nodes": [
+                   {"id": 3, "type": "workspace", "name": "1:web", "window": null,
+                    "nodes": [
+                      {"id": 4, "type": "con", "name": "Rust - Firefox", "window": 8388610,
+                       "window_properties": {"class": "firefox", "instance": "Navigator"},
+                       "nodes": []}],
+                    "floating_nodes": [
+                      {"id": 5, "type": "floating_con", "name": null, "nodes": [
+                        {"id": 6, "type": "con", "name": "foot", "app_id": "foot",
+                         "nodes": []}]}]}]},
+                 {"id": 7, "type": "output", "name": "__i3", "window": null, "nodes": [
+                   {"id": 8, "type": "workspace", "name": "__i3_scratch", "nodes": [
+                     {"id": 9, "type": "con", "name": "hidden", "window": 4,
+                      "nodes": []}]}]}]}"#,
+        )
+        .unwrap();
+        let mut windows = vec![];
+        windows_from_json(&tree, None, &mut windows);
+        assert_eq!(
+            windows,
+            vec![
+                Window {
+                    id: 4,
+                    workspace: String::from("1:web"),
+                    class: Some(String::from("firefox")),
+                    instance: Some(String::from("Navigator")),
+                    title: Some(String::from("Rust - Firefox")),
+                },
+                Window {
+                    id: 6,
+                    workspace: String::from("1:web"),
+                    class: Some(String::from("foot")),
+                    instance: None,
+                    title: Some(String::from("foot")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn window_events_are_decoded() {
+        assert_eq!(
+            window_event_from_json(br#"{"change": "new", "container": {"id": 42}}"#).unwrap(),
+            WmEvent::Window(WindowChange::New, 42)
+        );
+        assert!(window_event_from_json(br#"{"change": "new", "container": {}}"#).is_err());
+        assert!(window_event_from_json(b"{}").is_err());
+    }
+
+    #[test]
+    fn wm_names_parse() {
+        assert_eq!("i3".parse::<WmKind>().unwrap(), WmKind::I3);
+        assert_eq!("sway".parse::<WmKind>().unwrap(), WmKind::Sway);
+        assert!("dwm".parse::<WmKind>().is_err());
+    }
+}